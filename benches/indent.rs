@@ -0,0 +1,102 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The system allocator, counting the allocations made through it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Returns the number of allocations that `f` makes.
+fn allocations(mut f: impl FnMut()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Runs `f` `depth` frames deep.
+fn nested(depth: usize, f: &mut impl FnMut()) {
+    let _ = iprint::call_depth!();
+    if depth == 0 {
+        f();
+    } else {
+        nested(depth - 1, f);
+    }
+}
+
+fn indent(c: &mut Criterion) {
+    iprint::suppress_release_warning();
+    let mut group = c.benchmark_group("iformat at depth 8");
+    group.bench_function("cached prefix", |b| {
+        nested(8, &mut || {
+            b.iter(|| black_box(iprint::iformat!("a\nmulti-line\nmessage")))
+        })
+    });
+    group.bench_function("rebuilt prefix", |b| {
+        nested(8, &mut || {
+            b.iter(|| {
+                // Changing a setting invalidates the cached prefixes.
+                iprint::set_indent_width(4);
+                black_box(iprint::iformat!("a\nmulti-line\nmessage"))
+            })
+        })
+    });
+    group.finish();
+}
+
+fn indent_to(c: &mut Criterion) {
+    iprint::suppress_release_warning();
+    let mut buf = String::new();
+    nested(8, &mut || {
+        let allocating = allocations(|| {
+            black_box(iprint::iformat!("a\nmulti-line\nmessage {}", 42));
+        });
+        // The first call grows the buffer.
+        iprint::iformat_to!(&mut buf, "a\nmulti-line\nmessage {}", 42);
+        let reusing = allocations(|| {
+            buf.clear();
+            iprint::iformat_to!(&mut buf, "a\nmulti-line\nmessage {}", 42);
+        });
+        println!("allocations per call: iformat! {allocating}, iformat_to! {reusing}");
+        assert!(reusing < allocating);
+    });
+    let mut group = c.benchmark_group("formatting at depth 8");
+    group.bench_function("iformat", |b| {
+        nested(8, &mut || {
+            b.iter(|| black_box(iprint::iformat!("a\nmulti-line\nmessage {}", 42)))
+        })
+    });
+    group.bench_function("iformat_to reused buffer", |b| {
+        nested(8, &mut || {
+            b.iter(|| {
+                buf.clear();
+                iprint::iformat_to!(&mut buf, "a\nmulti-line\nmessage {}", 42);
+                black_box(&buf);
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, indent, indent_to);
+criterion_main!(benches);
@@ -0,0 +1,108 @@
+//! # iprint-macros
+//!
+//! Attribute macros for [`iprint`](https://docs.rs/iprint). They are
+//! re-exported by `iprint` when its `macros` feature is enabled, so there's
+//! usually no need to depend on this crate directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Block, ImplItem, ItemFn, ItemImpl};
+
+/// Indents a function by one level for as long as it runs.
+///
+/// The attribute inserts an `iprint::scope()` guard at the start of the
+/// function body. The guard is held until the function returns, including
+/// early `return`s and `?`, so the indentation is correct even in release mode.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iformat;
+/// use iprint_macros::indent;
+///
+/// #[indent]
+/// fn traced() -> String {
+///     iformat!("hi")
+/// }
+///
+/// fn main() {
+///     assert_eq!(traced(), "    hi");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn indent(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let attr = proc_macro2::TokenStream::from(attr);
+        return syn::Error::new_spanned(attr, "#[indent] does not take any arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let mut function = parse_macro_input!(item as ItemFn);
+    insert_guard(&mut function.block);
+    quote!(#function).into()
+}
+
+/// Indents every method of an `impl` block by one level for as long as it
+/// runs.
+///
+/// This is the same as putting [`#[indent]`](macro@indent) on each method of
+/// the block. Methods that already have an `#[indent]` attribute, and `const`
+/// methods, which can't create a guard, are left as they are.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iformat;
+/// use iprint_macros::indent_all;
+///
+/// struct Parser;
+///
+/// #[indent_all]
+/// impl Parser {
+///     fn parse(&self) -> Vec<String> {
+///         let mut lines = vec![iformat!("parse")];
+///         lines.push(self.token());
+///         lines
+///     }
+///
+///     fn token(&self) -> String {
+///         iformat!("token")
+///     }
+/// }
+///
+/// fn main() {
+///     assert_eq!(Parser.parse(), ["    parse", "        token"]);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn indent_all(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let attr = proc_macro2::TokenStream::from(attr);
+        return syn::Error::new_spanned(attr, "#[indent_all] does not take any arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let mut block = parse_macro_input!(item as ItemImpl);
+    for item in &mut block.items {
+        if let ImplItem::Fn(method) = item {
+            let indented = method.attrs.iter().any(|attr| {
+                attr.path()
+                    .segments
+                    .last()
+                    .is_some_and(|segment| segment.ident == "indent")
+            });
+            if !indented && method.sig.constness.is_none() {
+                insert_guard(&mut method.block);
+            }
+        }
+    }
+    quote!(#block).into()
+}
+
+fn insert_guard(block: &mut Block) {
+    block
+        .stmts
+        .insert(0, parse_quote!(let __iprint_guard = ::iprint::scope();));
+}
@@ -0,0 +1,166 @@
+use crate::IndentWriter;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+use std::thread::JoinHandle;
+
+/// A guard that indents everything the process writes to stdout while it
+/// is alive, returned by [`capture_stdout`].
+///
+/// Dropping the guard restores the original stdout and waits until all the
+/// captured output has been written to it. That is once the pipe is closed by
+/// every process writing to it, so dropping the guard blocks for as long as a
+/// child process that was spawned while capturing, and inherited the captured
+/// stdout, keeps running.
+///
+/// This type is available only if the "capture" feature is enabled, on unix.
+#[must_use = "stdout is restored as soon as the guard is dropped"]
+pub struct StdoutCapture {
+    saved: OwnedFd,
+    forward: Option<JoinHandle<()>>,
+}
+
+/// Indents everything written to stdout by called code that doesn't use
+/// iprint, until the returned guard is dropped.
+///
+/// The file descriptor of stdout is replaced with a pipe, and a background
+/// thread writes each line read from it to the original stdout, indented by
+/// the call depth of the calling function. This catches `println!` as well
+/// as output of C libraries, but is a best effort: the lines arrive on the
+/// original stdout slightly later than they were written, and output from
+/// the iprint macros is captured and indented again like all other output.
+///
+/// Child processes spawned while capturing inherit the pipe as their stdout,
+/// so their output is indented too. Dropping the guard waits for them to
+/// close it, so they should have exited by then, or have their stdout
+/// redirected elsewhere.
+///
+/// Only one capture should be active at a time.
+///
+/// # Errors
+///
+/// Returns an error if the pipe could not be created or stdout could not be
+/// redirected to it.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{capture_stdout, iformat};
+/// use std::process::Command;
+///
+/// fn third_party() {
+///     println!("loading");
+///     println!("done");
+/// }
+///
+/// fn nested() {
+///     let _capture = capture_stdout().unwrap();
+///     third_party();
+/// }
+///
+/// fn main() {
+///     if std::env::var_os("IPRINT_CHILD").is_some() {
+///         iformat!("top");
+///         nested();
+///         return;
+///     }
+///
+///     // Runs this example again, to read what it writes to stdout.
+///     let output = Command::new(std::env::current_exe().unwrap())
+///         .env("IPRINT_CHILD", "1")
+///         .output()
+///         .unwrap();
+///     assert_eq!(String::from_utf8(output.stdout).unwrap(), "    loading\n    done\n");
+/// }
+/// ```
+///
+/// This function is available only if the "capture" feature is enabled, on
+/// unix.
+#[inline(always)]
+pub fn capture_stdout() -> io::Result<StdoutCapture> {
+    capture_at(crate::call_depth!())
+}
+
+fn capture_at(depth: usize) -> io::Result<StdoutCapture> {
+    io::stdout().flush()?;
+    let (read, write) = pipe()?;
+    let saved = io::stdout().as_fd().try_clone_to_owned()?;
+    let out = saved.try_clone()?;
+    check(unsafe { libc::dup2(write.as_raw_fd(), libc::STDOUT_FILENO) })?;
+    // Only stdout keeps the write end open from here on, so the forwarding
+    // thread sees the end of the pipe once stdout is restored.
+    drop(write);
+    let forward = std::thread::spawn(move || {
+        let mut reader = File::from(read);
+        let mut writer = IndentWriter::with_depth(File::from(out), depth);
+        let _ = io::copy(&mut reader, &mut writer);
+        let _ = writer.flush();
+    });
+    Ok(StdoutCapture {
+        saved,
+        forward: Some(forward),
+    })
+}
+
+/// Opens a pipe whose ends are closed on `exec`.
+///
+/// Child processes only inherit the pipe through stdout, which `dup2` leaves
+/// inheritable. Otherwise a child spawned from another thread would keep the
+/// pipe open, and the forwarding thread would never see its end.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+))]
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    check(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) })?;
+    // SAFETY: the pipe was just opened, and nothing else owns its ends.
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Opens a pipe whose ends are closed on `exec`, on targets without `pipe2`
+/// like macOS, where they are briefly inheritable.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+)))]
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    // SAFETY: the pipe was just opened, and nothing else owns its ends.
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    for fd in [&read, &write] {
+        check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) })?;
+    }
+    Ok((read, write))
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+impl Drop for StdoutCapture {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        unsafe { libc::dup2(self.saved.as_raw_fd(), libc::STDOUT_FILENO) };
+        if let Some(forward) = self.forward.take() {
+            let _ = forward.join();
+        }
+    }
+}
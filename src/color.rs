@@ -0,0 +1,49 @@
+use crate::Stream;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+const PALETTE: [u8; 6] = [36, 32, 33, 35, 34, 31];
+
+/// Enables or disables coloring the printed output by call depth.
+///
+/// Each depth is printed in one of six ANSI colors, which makes it easier to
+/// follow the nesting in a terminal. Colors are enabled by default, but never
+/// emitted when the output stream is not a terminal. Only the printing macros
+/// are colored; the strings returned by [`iformat!`](crate::iformat) and the
+/// messages of the logging macros never contain escape codes.
+///
+/// # Example
+///
+/// ```
+/// #[cfg(feature = "color")]
+/// {
+///     iprint::set_color_enabled(false);
+///     assert!(!iprint::color_enabled());
+/// }
+/// ```
+///
+/// This function is available only if the "color" feature is enabled.
+pub fn set_color_enabled(enabled: bool) {
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether the printed output is colored by call depth.
+///
+/// This function is available only if the "color" feature is enabled.
+pub fn color_enabled() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn paint(text: String, depth: usize, stream: Stream) -> String {
+    let is_terminal = match stream {
+        Stream::Stdout => std::io::stdout().is_terminal(),
+        Stream::Stderr => std::io::stderr().is_terminal(),
+    };
+    if !color_enabled() || !is_terminal {
+        return text;
+    }
+    let color = PALETTE[depth % PALETTE.len()];
+    format!("\x1b[{}m{}\x1b[0m", color, text)
+}
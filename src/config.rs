@@ -0,0 +1,901 @@
+use crate::sync::Setting;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static INDENT_WIDTH: AtomicUsize = AtomicUsize::new(4);
+/// The indent width fixed at build time with `IPRINT_INDENT_WIDTH`.
+const BUILD_INDENT_WIDTH: Option<usize> = match option_env!("IPRINT_INDENT_WIDTH") {
+    Some(width) => Some(parse_width(width)),
+    None => None,
+};
+static INDENT_STR: Setting<Option<&'static str>> = Setting::new(None);
+static INDENT_CHAR: Setting<char> = Setting::new(' ');
+static INDENT_STYLE: Setting<IndentStyle> = Setting::new(IndentStyle::Spaces);
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(usize::MAX);
+static PRESERVE_LINE_ENDINGS: AtomicBool = AtomicBool::new(false);
+static INDENT_BLANK_LINES: AtomicBool = AtomicBool::new(true);
+static ESCAPE_CONTROL_CHARS: AtomicBool = AtomicBool::new(false);
+static GUTTER_ENABLED: AtomicBool = AtomicBool::new(false);
+static RULER_INTERVAL: Setting<Option<usize>> = Setting::new(None);
+static COLLAPSE_DEPTH: Setting<Option<usize>> = Setting::new(None);
+static CONTINUATION_EXTRA_INDENT: AtomicUsize = AtomicUsize::new(0);
+static MAX_LINE_LEN: AtomicUsize = AtomicUsize::new(usize::MAX);
+static WRAP_WIDTH: Setting<Option<usize>> = Setting::new(None);
+#[cfg(feature = "terminal-size")]
+static WRAP_TO_TERMINAL: AtomicBool = AtomicBool::new(false);
+static LINE_SEPARATOR: Setting<&'static str> = Setting::new("\n");
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "log")]
+static LOG_MAX_DEPTH: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Sets the number of spaces used for each level of indentation.
+///
+/// The width applies to every thread and to every indented macro invoked
+/// after the call. It defaults to 4. Setting a width switches back to padding
+/// with [`indent_char`] if a custom string was set with [`set_indent_str`].
+///
+/// The width can also be fixed at build time by setting the
+/// `IPRINT_INDENT_WIDTH` environment variable when compiling iprint, e.g.
+/// `IPRINT_INDENT_WIDTH=2 cargo build`. It is then a constant that is never
+/// loaded at runtime, and the width set with this function is ignored.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_indent_width};
+///
+/// fn main() {
+///     set_indent_width(2);
+///     assert_eq!(iformat!("top"), "top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("nested"), "  nested");
+/// }
+/// ```
+///
+/// The indentation of each depth is cached per thread, but every setting that
+/// affects it invalidates the caches of all threads, so a new width applies
+/// from the very next message on:
+///
+/// ```
+/// use iprint::{iprintln, set_indent_width, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("top");
+///     nested();
+///     set_indent_width(2);
+///     nested();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "top\n    nested\n  nested\n");
+/// }
+///
+/// fn nested() {
+///     iprintln!("nested");
+/// }
+/// ```
+pub fn set_indent_width(width: usize) {
+    INDENT_WIDTH.store(width, Ordering::Relaxed);
+    INDENT_STR.set(None);
+    bump_generation();
+}
+
+/// Returns the number of spaces used for each level of indentation.
+pub fn indent_width() -> usize {
+    match BUILD_INDENT_WIDTH {
+        Some(width) => width,
+        None => INDENT_WIDTH.load(Ordering::Relaxed),
+    }
+}
+
+/// Parses the decimal `IPRINT_INDENT_WIDTH`, failing the build if it isn't
+/// a number.
+const fn parse_width(width: &str) -> usize {
+    let digits = width.as_bytes();
+    assert!(!digits.is_empty(), "IPRINT_INDENT_WIDTH must be a number");
+    let mut value = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        assert!(
+            digits[i].is_ascii_digit(),
+            "IPRINT_INDENT_WIDTH must be a number"
+        );
+        value = value * 10 + (digits[i] - b'0') as usize;
+        i += 1;
+    }
+    value
+}
+
+/// Sets a custom string that is repeated once for each level of indentation.
+///
+/// This replaces the space padding, e.g. `"\t"` indents with one tab per
+/// level. The string is used until [`set_indent_width`] or [`set_indent_char`]
+/// is called again.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_indent_str};
+///
+/// fn main() {
+///     set_indent_str("\t");
+///     assert_eq!(iformat!("top"), "top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("a\nb"), "\ta\n\tb");
+/// }
+/// ```
+pub fn set_indent_str(indent: &'static str) {
+    INDENT_STR.set(Some(indent));
+    bump_generation();
+}
+
+/// Sets the character that fills the [`indent_width`] columns of each level.
+///
+/// This replaces the default space, e.g. `'.'` makes the indentation visible.
+/// Setting a character switches back to padding if a custom string was set
+/// with [`set_indent_str`].
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_indent_char};
+///
+/// fn main() {
+///     set_indent_char('.');
+///     assert_eq!(iformat!("top"), "top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("nested"), "....nested");
+///     deeper();
+/// }
+///
+/// fn deeper() {
+///     assert_eq!(iformat!("deeper"), "........deeper");
+/// }
+/// ```
+///
+/// Every character is counted as one column, unless the "unicode-width"
+/// feature is enabled. Then wide characters, like those of East Asian
+/// scripts, are counted by their display width, so the columns of every depth
+/// still line up in a terminal. A width that the character doesn't divide is
+/// filled up with spaces.
+///
+/// ```
+/// #[cfg(feature = "unicode-width")]
+/// {
+///     use iprint::{iformat, set_indent_char};
+///
+///     fn main() {
+///         // The ideographic space is two columns wide.
+///         set_indent_char('\u{3000}');
+///         iformat!("top");
+///         nested();
+///     }
+///
+///     fn nested() {
+///         iformat!("nested");
+///         deeper();
+///     }
+///
+///     fn deeper() {
+///         // Two levels of four columns each.
+///         assert_eq!(iformat!("deeper"), "\u{3000}\u{3000}\u{3000}\u{3000}deeper");
+///     }
+///
+///     main();
+/// }
+/// ```
+pub fn set_indent_char(indent: char) {
+    INDENT_CHAR.set(indent);
+    INDENT_STR.set(None);
+    bump_generation();
+}
+
+/// Returns the character that fills the columns of each level.
+pub fn indent_char() -> char {
+    INDENT_CHAR.get()
+}
+
+pub(crate) fn indent_str() -> Option<&'static str> {
+    INDENT_STR.get()
+}
+
+#[cfg(feature = "std")]
+type IndentFn = alloc::boxed::Box<dyn Fn(usize) -> alloc::string::String + Send + Sync>;
+
+#[cfg(feature = "std")]
+static INDENT_FN: std::sync::RwLock<Option<IndentFn>> = std::sync::RwLock::new(None);
+
+/// Sets a function that returns the prefix of each level of indentation.
+///
+/// The function is called with the index of every level, starting at 0 for
+/// the outermost one, and the prefixes it returns are concatenated. This
+/// allows levels to look different from each other, e.g. to tell even and odd
+/// levels apart. While it is set, it takes precedence over the [style],
+/// width, character and string of the indentation, and it is used for the
+/// continuation lines of a message as well. The function must not use the
+/// macros of this crate itself.
+///
+/// [style]: set_indent_style
+///
+/// # Example
+///
+/// ```
+/// use iprint::{clear_indent_fn, iformat, set_indent_fn};
+///
+/// fn main() {
+///     set_indent_fn(|level| if level % 2 == 0 { "| " } else { ": " }.to_string());
+///     iformat!("top");
+///     nested();
+///     clear_indent_fn();
+/// }
+///
+/// fn nested() {
+///     iformat!("nested");
+///     deeper();
+/// }
+///
+/// fn deeper() {
+///     assert_eq!(iformat!("a\nb"), "| : a\n| : b");
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_indent_fn(indent: impl Fn(usize) -> alloc::string::String + Send + Sync + 'static) {
+    *INDENT_FN
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(alloc::boxed::Box::new(indent));
+    bump_generation();
+}
+
+/// Removes the function set with [`set_indent_fn`], so the indentation is
+/// rendered by the [style](set_indent_style) again.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn clear_indent_fn() {
+    *INDENT_FN
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = None;
+    bump_generation();
+}
+
+/// Returns the prefix of `depth` levels built by the function set with
+/// [`set_indent_fn`], if there is one.
+#[cfg(feature = "std")]
+pub(crate) fn indent_fn_prefix(depth: usize) -> Option<alloc::string::String> {
+    let indent = INDENT_FN
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    indent
+        .as_ref()
+        .map(|indent| (0..depth).map(indent).collect())
+}
+
+/// The characters used to render each level of indentation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// Pads each level with [`indent_width`] spaces, or rather [`indent_char`]s,
+    /// or with the custom string set by [`set_indent_str`].
+    #[default]
+    Spaces,
+    /// Indents each level with a single tab.
+    Tabs,
+    /// Draws the nesting as a tree, like `│   ├── message`.
+    ///
+    /// Every level contributes a `│` guide line, and the first line of each
+    /// message ends in a `├──` connector. Each level is [`indent_width`]
+    /// columns wide.
+    Tree,
+    /// Marks every line with its depth, like `[L3] message`, instead of
+    /// padding it.
+    ///
+    /// This conveys the depth compactly in narrow columns. Lines at depth 0
+    /// are marked as well, with `[L0] `.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use iprint::{iformat, set_indent_style, IndentStyle};
+    ///
+    /// fn main() {
+    ///     set_indent_style(IndentStyle::Marker);
+    ///     assert_eq!(iformat!("root"), "[L0] root");
+    ///     a();
+    /// }
+    ///
+    /// fn a() {
+    ///     iformat!("a");
+    ///     b();
+    /// }
+    ///
+    /// fn b() {
+    ///     iformat!("b");
+    ///     c();
+    /// }
+    ///
+    /// fn c() {
+    ///     assert_eq!(iformat!("first\nsecond"), "[L3] first\n[L3] second");
+    /// }
+    /// ```
+    Marker,
+}
+
+/// Sets the style used to render the indentation.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_indent_style, IndentStyle};
+///
+/// fn main() {
+///     set_indent_style(IndentStyle::Tree);
+///     assert_eq!(iformat!("root"), "root");
+///     child();
+/// }
+///
+/// fn child() {
+///     assert_eq!(iformat!("child"), "├── child");
+///     grandchild();
+/// }
+///
+/// fn grandchild() {
+///     assert_eq!(iformat!("a\nb"), "│   ├── a\n│   │   b");
+/// }
+/// ```
+pub fn set_indent_style(style: IndentStyle) {
+    INDENT_STYLE.set(style);
+    bump_generation();
+}
+
+/// Returns the style used to render the indentation.
+pub fn indent_style() -> IndentStyle {
+    INDENT_STYLE.get()
+}
+
+/// Sets by how many extra levels the continuation lines of a message are
+/// indented.
+///
+/// The first line of a message is indented by its depth as usual, and every
+/// further line by `levels` more, which makes wrapped or multi-line content
+/// visually subordinate to the line that introduces it. It defaults to 0.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_continuation_extra_indent};
+///
+/// fn main() {
+///     set_continuation_extra_indent(1);
+///     assert_eq!(iformat!("top\nwrapped"), "top\n    wrapped");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("nested\nwrapped"), "    nested\n        wrapped");
+/// }
+/// ```
+pub fn set_continuation_extra_indent(levels: usize) {
+    CONTINUATION_EXTRA_INDENT.store(levels, Ordering::Relaxed);
+    bump_generation();
+}
+
+/// Returns by how many extra levels the continuation lines of a message are
+/// indented.
+pub fn continuation_extra_indent() -> usize {
+    CONTINUATION_EXTRA_INDENT.load(Ordering::Relaxed)
+}
+
+/// Sets every how many levels the indentation draws a vertical guide line.
+///
+/// With an interval of `n`, the last column of every `n`th level is drawn as
+/// a `¦` instead of padding, like the indent guides of an editor, which makes
+/// the levels of deep traces easy to count. This applies to the padding with
+/// [`indent_char`], but not to custom strings or other [`IndentStyle`]s.
+/// `None`, the default, and an interval of 0 draw no guides.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_ruler_interval};
+///
+/// fn main() {
+///     set_ruler_interval(Some(2));
+///     iformat!("top");
+///     a();
+/// }
+///
+/// fn a() {
+///     iformat!("a");
+///     b();
+/// }
+///
+/// fn b() {
+///     iformat!("b");
+///     c();
+/// }
+///
+/// fn c() {
+///     iformat!("c");
+///     d();
+/// }
+///
+/// fn d() {
+///     // The guides are in columns 8 and 16.
+///     assert_eq!(iformat!("d"), "       ¦       ¦d");
+/// }
+/// ```
+pub fn set_ruler_interval(interval: Option<usize>) {
+    RULER_INTERVAL.set(interval);
+    bump_generation();
+}
+
+/// Returns every how many levels the indentation draws a vertical guide line.
+pub fn ruler_interval() -> Option<usize> {
+    RULER_INTERVAL.get()
+}
+
+/// Enables or disables a gutter that shows the depth of every line as a
+/// number.
+///
+/// The gutter, like `  3│ `, is put in front of the indentation of each line,
+/// with the depth right-aligned in three columns. This makes the depth of
+/// lines in dense output easy to read off, also when the indentation itself
+/// is turned off with an [indent width](set_indent_width) of 0. It is
+/// disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_gutter_enabled};
+///
+/// fn main() {
+///     set_gutter_enabled(true);
+///     assert_eq!(iformat!("top"), "  0│ top");
+///     a();
+/// }
+///
+/// fn a() {
+///     iformat!("a");
+///     b();
+/// }
+///
+/// fn b() {
+///     iformat!("b");
+///     c();
+/// }
+///
+/// fn c() {
+///     assert_eq!(
+///         iformat!("first\nsecond"),
+///         "  3│             first\n  3│             second"
+///     );
+/// }
+/// ```
+pub fn set_gutter_enabled(enabled: bool) {
+    GUTTER_ENABLED.store(enabled, Ordering::Relaxed);
+    bump_generation();
+}
+
+/// Returns whether the depth of every line is shown in a gutter.
+pub fn gutter_enabled() -> bool {
+    GUTTER_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum number of indentation levels.
+///
+/// Output from deeper call depths is still printed, but indented as if it
+/// happened at `max_depth`. By default the indentation is unlimited.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_max_depth};
+///
+/// fn main() {
+///     set_max_depth(8);
+///     iformat!("top");
+///     recurse(20);
+/// }
+///
+/// fn recurse(n: usize) {
+///     let line = iformat!("level {}", n);
+///     let indent = line.len() - line.trim_start().len();
+///     assert!(indent <= 32);
+///     if n > 0 {
+///         recurse(n - 1);
+///     } else {
+///         assert_eq!(indent, 32);
+///     }
+/// }
+/// ```
+pub fn set_max_depth(max_depth: usize) {
+    MAX_DEPTH.store(max_depth, Ordering::Relaxed);
+    bump_generation();
+}
+
+/// Returns the maximum number of indentation levels.
+pub fn max_depth() -> usize {
+    MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Sets the depth past which the indentation collapses into a marker.
+///
+/// Unlike with [`set_max_depth`], output from deeper than `cap` levels is
+/// indented by `cap` levels followed by `… `, which tells it apart from
+/// output that actually happened at the cap. The continuation lines of such a
+/// message are aligned with its first line. `None`, the default, never
+/// collapses.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_collapse_depth};
+///
+/// set_collapse_depth(Some(3));
+/// assert_eq!(iformat!(depth = 3, "capped"), "            capped");
+/// assert_eq!(iformat!(depth = 10, "deep\ner"), "            … deep\n              er");
+/// ```
+pub fn set_collapse_depth(cap: Option<usize>) {
+    COLLAPSE_DEPTH.set(cap);
+    bump_generation();
+}
+
+/// Returns the depth past which the indentation collapses into a marker.
+pub fn collapse_depth() -> Option<usize> {
+    COLLAPSE_DEPTH.get()
+}
+
+/// Sets whether indenting keeps the original line endings of a message.
+///
+/// By default a message is split like [`str::lines`], so `\r\n` line endings
+/// come out as `\n` and a single trailing line ending is dropped. With the
+/// line endings preserved, every line keeps its terminator as it was,
+/// including a trailing one, and only the indentation is added.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_preserve_line_endings};
+///
+/// fn main() {
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("a\r\nb\r\n"), "    a\n    b");
+///     set_preserve_line_endings(true);
+///     assert_eq!(iformat!("a\r\nb\r\n"), "    a\r\n    b\r\n");
+///     assert_eq!(iformat!("a\nb"), "    a\n    b");
+/// }
+/// ```
+pub fn set_preserve_line_endings(preserve: bool) {
+    PRESERVE_LINE_ENDINGS.store(preserve, Ordering::Relaxed);
+}
+
+/// Returns whether indenting keeps the original line endings of a message.
+pub fn preserve_line_endings() -> bool {
+    PRESERVE_LINE_ENDINGS.load(Ordering::Relaxed)
+}
+
+/// Sets the separator that joins the indented lines of a message.
+///
+/// A message is still split into lines at its line endings, but the
+/// indented lines are joined with `separator` instead of `\n`. This allows
+/// e.g. keeping a multi-line message on a single line for a log shipper. It
+/// defaults to `"\n"`, and doesn't apply while the original line endings
+/// are [preserved](set_preserve_line_endings).
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_line_separator};
+///
+/// fn main() {
+///     set_line_separator(" | ");
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("a\nb\nc"), "    a |     b |     c");
+/// }
+/// ```
+pub fn set_line_separator(separator: &'static str) {
+    LINE_SEPARATOR.set(separator);
+}
+
+/// Returns the separator that joins the indented lines of a message.
+pub fn line_separator() -> &'static str {
+    LINE_SEPARATOR.get()
+}
+
+/// Sets whether empty lines of a message are indented too.
+///
+/// By default every line is padded, so empty lines end up consisting only of
+/// the indentation. Disabling this leaves them empty, which avoids trailing
+/// whitespace in captured output.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_indent_blank_lines};
+///
+/// fn main() {
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("a\n\nb"), "    a\n    \n    b");
+///     set_indent_blank_lines(false);
+///     assert_eq!(iformat!("a\n\nb"), "    a\n\n    b");
+/// }
+/// ```
+pub fn set_indent_blank_lines(indent: bool) {
+    INDENT_BLANK_LINES.store(indent, Ordering::Relaxed);
+}
+
+/// Returns whether empty lines of a message are indented too.
+pub fn indent_blank_lines() -> bool {
+    INDENT_BLANK_LINES.load(Ordering::Relaxed)
+}
+
+/// Sets whether control characters in a message are replaced with escapes.
+///
+/// Every control character other than a line break, like the escape
+/// character or a tab, is written as a hexadecimal escape like `\x1b` or
+/// `\u{85}` before the message is indented. This keeps the traced contents of
+/// untrusted or binary strings from corrupting the terminal. A line feed
+/// still starts a new indented line, and so does a carriage return right in
+/// front of it. By default a message is left as it is.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_escape_control_chars};
+///
+/// fn main() {
+///     set_escape_control_chars(true);
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     let data = "\x1b[31mred\tcell\0\nnext";
+///     assert_eq!(iformat!("{}", data), "    \\x1b[31mred\\x09cell\\x00\n    next");
+/// }
+/// ```
+pub fn set_escape_control_chars(escape: bool) {
+    ESCAPE_CONTROL_CHARS.store(escape, Ordering::Relaxed);
+}
+
+/// Returns whether control characters in a message are replaced with escapes.
+pub fn escape_control_chars() -> bool {
+    ESCAPE_CONTROL_CHARS.load(Ordering::Relaxed)
+}
+
+/// Sets the maximum length of an indented line, in characters.
+///
+/// Longer lines are cut after `max_len` characters, counting the
+/// indentation, and end in an ellipsis `…` instead. This keeps huge values
+/// from flooding the terminal. By default lines are never truncated.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_max_line_len};
+///
+/// fn main() {
+///     set_max_line_len(10);
+///     assert_eq!(iformat!("0123456789abcdef\nshort"), "0123456789…\nshort");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("äöüßäöüßäöüß"), "    äöüßäö…");
+/// }
+/// ```
+pub fn set_max_line_len(max_len: usize) {
+    MAX_LINE_LEN.store(max_len, Ordering::Relaxed);
+}
+
+/// Returns the maximum length of an indented line, in characters.
+pub fn max_line_len() -> usize {
+    MAX_LINE_LEN.load(Ordering::Relaxed)
+}
+
+/// Sets the number of columns to wrap long lines at, or disables wrapping at a
+/// fixed width with `None`.
+///
+/// Every line of a message that doesn't fit into `width` columns, indentation
+/// included, is broken into segments that do, and each segment is indented
+/// like a continuation line, so a deep and long message stays readable. Lines
+/// are wrapped before they are cut down to the
+/// [maximum length](set_max_line_len). A width set here takes precedence over
+/// the width of the terminal. This is disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_wrap_width};
+///
+/// fn main() {
+///     set_wrap_width(Some(40));
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     iformat!("nested");
+///     deeper();
+/// }
+///
+/// fn deeper() {
+///     let message = "x".repeat(50);
+///     let wrapped = iformat!("{}", message);
+///     let lines: Vec<_> = wrapped.lines().collect();
+///     assert_eq!(lines, [format!("        {}", &message[..32]), format!("        {}", &message[32..])]);
+/// }
+/// ```
+pub fn set_wrap_width(width: Option<usize>) {
+    WRAP_WIDTH.set(width);
+}
+
+/// Enables or disables wrapping long lines at the width of the terminal.
+///
+/// This wraps lines like [`set_wrap_width`], at the width of the terminal
+/// stdout is connected to, unless a width was set there. The width is read at
+/// every call, and is assumed to be 80 columns if stdout isn't a terminal.
+/// This is disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_wrap_to_terminal, wrap_width};
+///
+/// fn main() {
+///     set_wrap_to_terminal(true);
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     iformat!("nested");
+///     deeper();
+/// }
+///
+/// fn deeper() {
+///     // The width depends on where the doctest runs.
+///     let width = wrap_width().unwrap();
+///     let message = "x".repeat(width + 1);
+///     let wrapped = iformat!("{}", message);
+///     assert!(wrapped.lines().count() > 1);
+///     assert!(wrapped.lines().all(|line| line.chars().count() <= width));
+///     assert_eq!(wrapped.replace("\n", "").replace(' ', ""), message);
+/// }
+/// ```
+///
+/// This function is available only if the "terminal-size" feature is enabled.
+#[cfg(feature = "terminal-size")]
+pub fn set_wrap_to_terminal(enabled: bool) {
+    WRAP_TO_TERMINAL.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether long lines are wrapped at the width of the terminal.
+///
+/// This function is available only if the "terminal-size" feature is enabled.
+#[cfg(feature = "terminal-size")]
+pub fn wrap_to_terminal() -> bool {
+    WRAP_TO_TERMINAL.load(Ordering::Relaxed)
+}
+
+/// Returns the number of columns long lines are wrapped at, if any.
+///
+/// This is the width set with [`set_wrap_width`] if there is one, and
+/// otherwise the width of the terminal if
+/// [wrapping at it](set_wrap_to_terminal) is enabled.
+pub fn wrap_width() -> Option<usize> {
+    if let Some(width) = WRAP_WIDTH.get() {
+        return Some(width);
+    }
+    #[cfg(feature = "terminal-size")]
+    if wrap_to_terminal() {
+        let width = terminal_size::terminal_size().map_or(80, |(width, _)| width.0);
+        return Some(usize::from(width));
+    }
+    None
+}
+
+/// Sets the deepest call depth at which the logging macros still log.
+///
+/// Messages from deeper call depths are skipped entirely, without evaluating
+/// their format arguments. By default every depth is logged.
+///
+/// # Example
+///
+/// ```
+/// use iprint::ilog::log::{self, LevelFilter, Log, Metadata, Record};
+/// use iprint::{iinfo, set_log_max_depth};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Mutex;
+///
+/// static LOGGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// static EVALUATED: AtomicUsize = AtomicUsize::new(0);
+///
+/// struct Recorder;
+///
+/// impl Log for Recorder {
+///     fn enabled(&self, _: &Metadata) -> bool {
+///         true
+///     }
+///
+///     fn log(&self, record: &Record) {
+///         LOGGED.lock().unwrap().push(record.args().to_string());
+///     }
+///
+///     fn flush(&self) {}
+/// }
+///
+/// fn expensive() -> u32 {
+///     EVALUATED.fetch_add(1, Ordering::Relaxed);
+///     42
+/// }
+///
+/// fn main() {
+///     log::set_logger(&Recorder).unwrap();
+///     log::set_max_level(LevelFilter::Info);
+///     // Only log from the top two levels of the call stack.
+///     set_log_max_depth(1);
+///     iinfo!("top");
+///     child();
+///     assert_eq!(*LOGGED.lock().unwrap(), ["top", "    child"]);
+///     assert_eq!(EVALUATED.load(Ordering::Relaxed), 0);
+/// }
+///
+/// fn child() {
+///     iinfo!("child");
+///     grandchild();
+/// }
+///
+/// fn grandchild() {
+///     iinfo!("value {}", expensive());
+/// }
+/// ```
+///
+/// This function is available only if the "log" feature is enabled.
+#[cfg(feature = "log")]
+pub fn set_log_max_depth(max_depth: usize) {
+    LOG_MAX_DEPTH.store(max_depth, Ordering::Relaxed);
+}
+
+/// Returns the deepest call depth at which the logging macros still log.
+///
+/// This function is available only if the "log" feature is enabled.
+#[cfg(feature = "log")]
+pub fn log_max_depth() -> usize {
+    LOG_MAX_DEPTH.load(Ordering::Relaxed)
+}
+
+/// Returns a counter that changes whenever a setting that affects the
+/// indentation prefix changes, so cached prefixes can be invalidated.
+///
+/// It's a `usize` rather than a `u64`, since targets like `thumbv7em` lack
+/// 64-bit atomics, and wrapping around would take billions of changes.
+pub(crate) fn generation() -> usize {
+    GENERATION.load(Ordering::Acquire)
+}
+
+fn bump_generation() {
+    GENERATION.fetch_add(1, Ordering::Release);
+}
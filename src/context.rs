@@ -0,0 +1,80 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+static CONTEXTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn lock_contexts() -> MutexGuard<'static, Vec<String>> {
+    CONTEXTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Enters a named logical context, which indents the output of
+/// [`iformat_ctx!`](crate::iformat_ctx) by one more level.
+///
+/// The contexts form a single stack that is shared by all threads, and is
+/// independent of the call depth. This suits logical operations that span
+/// the threads of a pool, where the physical call depth is meaningless. Every
+/// call has to be matched by a call to [`pop_context`].
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat_ctx, pop_context, push_context};
+/// use std::thread;
+///
+/// thread::spawn(|| push_context("request")).join().unwrap();
+/// thread::spawn(|| push_context("parse")).join().unwrap();
+/// assert_eq!(iformat_ctx!("tokens"), "        tokens");
+/// let line = thread::spawn(|| iformat_ctx!("tokens")).join().unwrap();
+/// assert_eq!(line, "        tokens");
+///
+/// assert_eq!(pop_context().as_deref(), Some("parse"));
+/// assert_eq!(pop_context().as_deref(), Some("request"));
+/// assert_eq!(iformat_ctx!("done"), "done");
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn push_context(name: impl Into<String>) {
+    lock_contexts().push(name.into());
+}
+
+/// Leaves the innermost logical context entered with [`push_context`], and
+/// returns its name, or `None` if there is none.
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn pop_context() -> Option<String> {
+    lock_contexts().pop()
+}
+
+/// Returns the number of logical contexts entered with [`push_context`].
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn context_depth() -> usize {
+    lock_contexts().len()
+}
+
+/// Returns the names of the entered logical contexts, from the outermost to
+/// the innermost one.
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn contexts() -> Vec<String> {
+    lock_contexts().clone()
+}
+
+/// Formats a given string with indentation based on the number of logical
+/// contexts entered with [`push_context`](crate::push_context).
+///
+/// This is the counterpart of [`iformat!`](crate::iformat) that ignores the
+/// call depth entirely. See [`push_context`](crate::push_context).
+///
+/// This macro is available only if the "std" feature is enabled.
+#[macro_export]
+macro_rules! iformat_ctx {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{
+            $crate::indent_text($crate::context_depth(), &$crate::format!($($t)*))
+        } else {
+            $crate::format!($($t)*)
+        }}
+    };
+}
@@ -0,0 +1,100 @@
+//! The entries printed by [`idump!`](crate::idump).
+//!
+//! Maps and other collections are told apart at compile time with two traits:
+//! method resolution picks [`DumpMap`] for a map before it tries the autoref
+//! [`DumpSeq`] implements for every iterable reference.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use std::collections::{BTreeMap, HashMap};
+
+#[doc(hidden)]
+pub trait DumpMap {
+    fn dump_entries(&self) -> Vec<String>;
+}
+
+impl<K: Debug, V: Debug, S> DumpMap for HashMap<K, V, S> {
+    fn dump_entries(&self) -> Vec<String> {
+        self.iter()
+            .map(|(key, value)| alloc::format!("{:#?} => {:#?}", key, value))
+            .collect()
+    }
+}
+
+impl<K: Debug, V: Debug> DumpMap for BTreeMap<K, V> {
+    fn dump_entries(&self) -> Vec<String> {
+        self.iter()
+            .map(|(key, value)| alloc::format!("{:#?} => {:#?}", key, value))
+            .collect()
+    }
+}
+
+#[doc(hidden)]
+pub trait DumpSeq {
+    fn dump_entries(&self) -> Vec<String>;
+}
+
+impl<'a, T: ?Sized> DumpSeq for &'a T
+where
+    &'a T: IntoIterator,
+    <&'a T as IntoIterator>::Item: Debug,
+{
+    fn dump_entries(&self) -> Vec<String> {
+        self.into_iter()
+            .map(|item| alloc::format!("{:#?}", item))
+            .collect()
+    }
+}
+
+/// Prints every entry of a collection on its own line, one level deeper than
+/// the current indentation.
+///
+/// Each item is printed with `{:#?}` like [`iprintln!`](crate::iprintln)
+/// would, so the lines of a multi-line `Debug` representation stay aligned.
+/// The entries of a `HashMap` or a `BTreeMap` are printed as `key => value`.
+/// The collection is only borrowed, and anything that can be iterated by
+/// reference works, like a `Vec`, a slice or a set.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{idump, iprintln, set_output_sink};
+/// use std::collections::BTreeMap;
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("numbers:");
+///     idump!(vec![1, 2, 3]);
+///
+///     let ages = BTreeMap::from([("alice", 31), ("bob", 27)]);
+///     idump!(ages);
+///
+///     assert_eq!(
+///         *CAPTURED.lock().unwrap(),
+///         "numbers:\n    1\n    2\n    3\n    \"alice\" => 31\n    \"bob\" => 27\n"
+///     );
+/// }
+/// ```
+#[macro_export]
+macro_rules! idump {
+    ($collection:expr $(,)?) => {
+        $crate::if_enabled! {{{
+            #[allow(unused_imports)]
+            use $crate::{DumpMap as _, DumpSeq as _};
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                for entry in (&$collection).dump_entries() {
+                    $crate::emit(call_depth + 1, &entry, $crate::Stream::Stdout, true, (file!(), line!()));
+                }
+            }
+        }} else {{
+            if false {
+                let _ = &$collection;
+            }
+        }}}
+    };
+}
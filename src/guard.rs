@@ -0,0 +1,375 @@
+use crate::sync::local;
+use core::cell::Cell;
+use core::marker::PhantomData;
+
+local!(
+    static DEPTH: Cell<usize> = Cell::new(0)
+);
+
+/// Increases the call depth of the current thread for as long as it is alive.
+///
+/// While at least one guard is alive, [`call_depth!`](crate::call_depth) reports
+/// the number of alive guards, plus any levels entered with [`indent()`], instead of inspecting the stack pointer, which
+/// keeps the indentation reliable in release builds. Create it with [`scope`]
+/// and keep it alive until the end of the function; dropping it restores the
+/// previous depth.
+///
+/// A panic that unwinds through a guard drops it as well, so catching the
+/// panic leaves no stale depth behind. The same holds for the frames that
+/// [`call_depth!`](crate::call_depth) records from the stack pointer: frames
+/// of the unwound calls are discarded the next time a shallower frame asks
+/// for its depth.
+///
+/// ```
+/// use iprint::{call_depth, iformat, scope};
+/// use std::panic;
+///
+/// fn main() {
+///     panic::set_hook(Box::new(|_| {}));
+///     assert_eq!(iformat!("top"), "top");
+///     assert!(panic::catch_unwind(|| recurse(3)).is_err());
+///     assert_eq!(call_depth!(), 0);
+///     nested();
+///     guarded();
+/// }
+///
+/// fn recurse(n: usize) {
+///     iformat!("level {}", n);
+///     if n == 0 {
+///         panic!("boom");
+///     }
+///     recurse(n - 1);
+/// }
+///
+/// fn nested() {
+///     assert_eq!(call_depth!(), 1);
+/// }
+///
+/// fn guarded() {
+///     let _guard = scope();
+///     let result = panic::catch_unwind(|| {
+///         let _inner = scope();
+///         assert_eq!(call_depth!(), 2);
+///         panic!("boom");
+///     });
+///     assert!(result.is_err());
+///     assert_eq!(call_depth!(), 1);
+/// }
+/// ```
+///
+/// Since the depth belongs to the thread that created the guard, the guard
+/// can't be sent to another thread:
+///
+/// ```compile_fail
+/// let guard = iprint::scope();
+/// std::thread::spawn(move || drop(guard));
+/// ```
+#[must_use = "the depth is restored as soon as the guard is dropped"]
+pub struct DepthGuard {
+    _private: PhantomData<*const ()>,
+}
+
+/// Enters a new indentation level that lasts until the returned guard is dropped.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{call_depth, iformat, scope};
+///
+/// fn main() {
+///     assert_eq!(call_depth!(), 0);
+///     traced();
+/// }
+///
+/// fn traced() {
+///     let _guard = scope();
+///     assert_eq!(call_depth!(), 1);
+///     nested();
+/// }
+///
+/// fn nested() {
+///     let _guard = scope();
+///     assert_eq!(iformat!("nested"), "        nested");
+/// }
+/// ```
+pub fn scope() -> DepthGuard {
+    indent();
+    DepthGuard {
+        _private: PhantomData,
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        dedent();
+    }
+}
+
+/// Increases the explicit call depth of the current thread by one level.
+///
+/// This is the manual counterpart of [`scope`] for code where a guard doesn't
+/// fit, and every call has to be matched by a call to [`dedent`]. While the
+/// explicit depth is non-zero, [`call_depth!`](crate::call_depth) reports it
+/// instead of inspecting the stack pointer.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{dedent, iformat, indent};
+///
+/// indent();
+/// indent();
+/// assert_eq!(iformat!("x"), "        x");
+/// dedent();
+/// assert_eq!(iformat!("x"), "    x");
+/// dedent();
+/// ```
+pub fn indent() {
+    DEPTH.with(|d| d.set(d.get() + 1));
+    #[cfg(feature = "std")]
+    if cfg!(feature = "enabled") {
+        crate::output::send_event(|| {
+            crate::TraceEvent::Enter(crate::offset_depth(explicit_depth()))
+        });
+    }
+}
+
+/// Decreases the explicit call depth of the current thread by one level.
+///
+/// See [`indent()`].
+pub fn dedent() {
+    #[cfg(feature = "std")]
+    if cfg!(feature = "enabled") && DEPTH.with(|d| d.get()) > 0 {
+        crate::output::send_event(|| {
+            crate::TraceEvent::Exit(crate::offset_depth(explicit_depth()))
+        });
+    }
+    DEPTH.with(|d| d.set(d.get().saturating_sub(1)));
+}
+
+#[doc(hidden)]
+pub fn explicit_depth() -> usize {
+    let depth = DEPTH.with(|d| d.get());
+    #[cfg(feature = "async")]
+    let depth = depth + crate::task::async_depth();
+    depth
+}
+
+/// Prints an entry banner, and an exit banner once dropped.
+///
+/// The lines printed in between are indented by one more level. Create it
+/// with [`itrace_scope!`](crate::itrace_scope). Like [`DepthGuard`], it stays
+/// on the thread that created it, whose depth offset it raises.
+#[cfg(feature = "std")]
+#[must_use = "the exit banner is printed as soon as the guard is dropped"]
+pub struct TraceGuard {
+    depth: usize,
+    name: alloc::string::String,
+    location: (&'static str, u32),
+    module: &'static str,
+    _thread: PhantomData<*const ()>,
+    #[cfg(feature = "otel")]
+    _span: Option<crate::otel::SpanScope>,
+}
+
+#[cfg(feature = "std")]
+impl TraceGuard {
+    #[doc(hidden)]
+    pub fn enter(
+        depth: usize,
+        name: alloc::string::String,
+        location: (&'static str, u32),
+        module: &'static str,
+    ) -> TraceGuard {
+        if cfg!(feature = "enabled") {
+            if crate::module_enabled(module) {
+                crate::output::println_at(
+                    depth,
+                    &alloc::format!("-> {}", name),
+                    crate::Stream::Stdout,
+                    location,
+                );
+            }
+            crate::set_depth_offset(crate::depth_offset() + 1);
+        }
+        TraceGuard {
+            depth,
+            #[cfg(feature = "otel")]
+            _span: cfg!(feature = "enabled")
+                .then(|| crate::otel::SpanScope::start(&name, location)),
+            name,
+            location,
+            module,
+            _thread: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        if cfg!(feature = "enabled") {
+            crate::set_depth_offset(crate::depth_offset() - 1);
+            if crate::module_enabled(self.module) {
+                crate::output::println_at(
+                    self.depth,
+                    &alloc::format!("<- {}", self.name),
+                    crate::Stream::Stdout,
+                    self.location,
+                );
+            }
+        }
+    }
+}
+
+/// Prints labeled entry and exit banners around the rest of a scope.
+///
+/// This macro prints `-> name` at the current indentation and returns a
+/// [`TraceGuard`] that prints `<- name` at the same indentation when dropped.
+/// Everything printed while the guard is alive is indented by one more level,
+/// on top of the call depth, by raising the [`depth_offset`](crate::depth_offset).
+/// The name accepts the same arguments as `format!`, or a single expression
+/// that implements `Display`, like [`ifn!()`](crate::ifn) for the name of the
+/// enclosing function.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, itrace_scope};
+///
+/// fn main() {
+///     let _scope = itrace_scope!("main");
+///     assert_eq!(iformat!("inside"), "    inside");
+///     parse(3);
+/// }
+///
+/// fn parse(n: u32) {
+///     let _scope = itrace_scope!("parse({})", n);
+///     assert_eq!(iformat!("parsing"), "            parsing");
+/// }
+/// ```
+///
+/// Prints:
+///
+/// ```text
+/// -> main
+///         -> parse(3)
+///         <- parse(3)
+/// <- main
+/// ```
+///
+/// Like the printing macros, the banners are left out in modules excluded by
+/// the [module filter](crate::set_module_filter), while the lines in between
+/// are still indented:
+///
+/// ```
+/// use iprint::{iprintln, itrace_scope, set_module_filter, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// mod net {
+///     pub fn connect() {
+///         iprint::iprintln!("connecting");
+///     }
+/// }
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     set_module_filter(&format!("{}::net", module_path!()));
+///     {
+///         let _scope = itrace_scope!("main");
+///         net::connect();
+///     }
+///     assert_eq!(*CAPTURED.lock().unwrap(), "        connecting\n");
+/// }
+/// ```
+///
+/// The guard can't be sent to another thread:
+///
+/// ```compile_fail
+/// let guard = iprint::itrace_scope!("main");
+/// std::thread::spawn(move || drop(guard));
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! itrace_scope {
+    ($format:literal $($t:tt)*) => {
+        $crate::TraceGuard::enter($crate::call_depth!(), $crate::format!($format $($t)*), (file!(), line!()), ::core::module_path!())
+    };
+    ($name:expr) => {
+        $crate::TraceGuard::enter($crate::call_depth!(), $crate::format!("{}", $name), (file!(), line!()), ::core::module_path!())
+    };
+}
+
+/// Indents by extra levels, on top of the call depth, for as long as it is
+/// alive.
+///
+/// Create it with [`extra_indent`] or [`with_extra_indent!`](crate::with_extra_indent).
+/// Like the depth offset it changes, it stays on the thread that created it.
+#[must_use = "the extra indentation is removed as soon as the guard is dropped"]
+pub struct ExtraIndentGuard {
+    levels: isize,
+    _thread: PhantomData<*const ()>,
+}
+
+/// Raises the [depth offset](crate::set_depth_offset) of the current thread by
+/// `levels` until the returned guard is dropped.
+///
+/// This indents the output of a part of a function without moving it into a
+/// function of its own.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{extra_indent, iformat};
+///
+/// fn main() {
+///     iformat!("top");
+///     for i in 0..2 {
+///         let _guard = extra_indent(1);
+///         assert_eq!(iformat!("item {}", i), format!("    item {}", i));
+///     }
+///     assert_eq!(iformat!("done"), "done");
+/// }
+/// ```
+pub fn extra_indent(levels: usize) -> ExtraIndentGuard {
+    let levels = isize::try_from(levels).unwrap_or(isize::MAX);
+    crate::set_depth_offset(crate::depth_offset().saturating_add(levels));
+    ExtraIndentGuard {
+        levels,
+        _thread: PhantomData,
+    }
+}
+
+impl Drop for ExtraIndentGuard {
+    fn drop(&mut self) {
+        crate::set_depth_offset(crate::depth_offset().saturating_sub(self.levels));
+    }
+}
+
+/// Runs a block with its output indented by extra levels.
+///
+/// The extra indentation is removed when the block is left, including by
+/// `return`, `?` or a panic, and the value of the block is returned. See
+/// [`extra_indent`].
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, with_extra_indent};
+///
+/// fn main() {
+///     iformat!("top");
+///     let line = with_extra_indent!(2, { iformat!("shifted") });
+///     assert_eq!(line, "        shifted");
+///     assert_eq!(iformat!("top"), "top");
+/// }
+/// ```
+#[macro_export]
+macro_rules! with_extra_indent {
+    ($levels:expr, $body:block) => {{
+        let _extra_indent = $crate::extra_indent($levels);
+        $body
+    }};
+}
@@ -0,0 +1,223 @@
+//! Indented versions of the `tracing` crate macros.
+//!
+//! Instead of the stack pointer, these macros indent by the number of
+//! `tracing` spans entered on the current thread. This keeps the indentation
+//! meaningful regardless of inlining and across `.await` points, as long as
+//! [`IndentLayer`] is part of the subscriber.
+//!
+//! Like the printing macros, they record no event in modules excluded by the
+//! [module filter](crate::set_module_filter). Without the "enabled" feature,
+//! the events are still recorded, with the message unindented.
+//!
+//! # Example
+//!
+//! ```
+//! #[cfg(feature = "tracing")]
+//! {
+//!     use iprint::itracing::{span_depth, IndentLayer};
+//!     use iprint::itracing_info;
+//!     use tracing::instrument;
+//!     use tracing_subscriber::layer::SubscriberExt;
+//!
+//!     #[instrument]
+//!     fn outer() {
+//!         assert_eq!(span_depth(), 1);
+//!         itracing_info!("indented by one level");
+//!         inner();
+//!     }
+//!
+//!     #[instrument]
+//!     fn inner() {
+//!         assert_eq!(span_depth(), 2);
+//!         itracing_info!("indented by two levels");
+//!     }
+//!
+//!     let subscriber = tracing_subscriber::registry().with(IndentLayer);
+//!     tracing::subscriber::with_default(subscriber, outer);
+//! }
+//! ```
+//!
+//! With a module filter, the events of other modules are left out:
+//!
+//! ```
+//! #[cfg(all(feature = "tracing", feature = "enabled"))]
+//! {
+//!     use iprint::itracing::IndentLayer;
+//!     use iprint::{itracing_info, set_module_filter};
+//!     use std::sync::atomic::{AtomicUsize, Ordering};
+//!     use tracing::{Event, Subscriber};
+//!     use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+//!
+//!     static EVENTS: AtomicUsize = AtomicUsize::new(0);
+//!
+//!     struct CountLayer;
+//!
+//!     impl<S: Subscriber> Layer<S> for CountLayer {
+//!         fn on_event(&self, _event: &Event<'_>, _ctx: Context<'_, S>) {
+//!             EVENTS.fetch_add(1, Ordering::Relaxed);
+//!         }
+//!     }
+//!
+//!     mod net {
+//!         pub fn connect() {
+//!             iprint::itracing_info!("connecting");
+//!         }
+//!     }
+//!
+//!     let subscriber = tracing_subscriber::registry()
+//!         .with(IndentLayer)
+//!         .with(CountLayer);
+//!     tracing::subscriber::with_default(subscriber, || {
+//!         set_module_filter(&format!("{}::net", module_path!()));
+//!         itracing_info!("starting");
+//!         net::connect();
+//!     });
+//!     assert_eq!(EVENTS.load(Ordering::Relaxed), 1);
+//! }
+//! ```
+//!
+//! This module is available only if the "tracing" feature is enabled.
+
+use std::cell::Cell;
+use tracing::span::Id;
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+
+#[doc(hidden)]
+pub use tracing;
+
+thread_local!(
+    static SPAN_DEPTH: Cell<usize> = const { Cell::new(0) }
+);
+
+/// A `tracing_subscriber` layer that counts the spans entered on each thread.
+///
+/// The count is reported by [`span_depth`] and used by the `itracing_*` macros.
+pub struct IndentLayer;
+
+impl<S: Subscriber> Layer<S> for IndentLayer {
+    fn on_enter(&self, _id: &Id, _ctx: Context<'_, S>) {
+        SPAN_DEPTH.with(|d| d.set(d.get() + 1));
+    }
+
+    fn on_exit(&self, _id: &Id, _ctx: Context<'_, S>) {
+        SPAN_DEPTH.with(|d| d.set(d.get().saturating_sub(1)));
+    }
+}
+
+/// Returns the number of spans currently entered on this thread.
+///
+/// This is only tracked while [`IndentLayer`] is part of the active subscriber.
+pub fn span_depth() -> usize {
+    SPAN_DEPTH.with(|d| d.get())
+}
+
+/// Records a trace event with a message indented by the span depth.
+///
+/// This macro is an enhanced version of the `trace!` macro from the `tracing`
+/// crate, adding automatic indentation based on [`span_depth`].
+///
+/// This macro is available only if the "tracing" feature is enabled.
+#[macro_export]
+macro_rules! itracing_trace {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::itracing::tracing::trace!(
+                    "{}",
+                    $crate::indent_text($crate::itracing::span_depth(), &$crate::format!($($t)*))
+                )
+            }
+        } else {
+            $crate::itracing::tracing::trace!("{}", $crate::format!($($t)*))
+        }}
+    }
+}
+
+/// Records a debug event with a message indented by the span depth.
+///
+/// This macro is an enhanced version of the `debug!` macro from the `tracing`
+/// crate, adding automatic indentation based on [`span_depth`].
+///
+/// This macro is available only if the "tracing" feature is enabled.
+#[macro_export]
+macro_rules! itracing_debug {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::itracing::tracing::debug!(
+                    "{}",
+                    $crate::indent_text($crate::itracing::span_depth(), &$crate::format!($($t)*))
+                )
+            }
+        } else {
+            $crate::itracing::tracing::debug!("{}", $crate::format!($($t)*))
+        }}
+    }
+}
+
+/// Records an info event with a message indented by the span depth.
+///
+/// This macro is an enhanced version of the `info!` macro from the `tracing`
+/// crate, adding automatic indentation based on [`span_depth`].
+///
+/// This macro is available only if the "tracing" feature is enabled.
+#[macro_export]
+macro_rules! itracing_info {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::itracing::tracing::info!(
+                    "{}",
+                    $crate::indent_text($crate::itracing::span_depth(), &$crate::format!($($t)*))
+                )
+            }
+        } else {
+            $crate::itracing::tracing::info!("{}", $crate::format!($($t)*))
+        }}
+    }
+}
+
+/// Records a warning event with a message indented by the span depth.
+///
+/// This macro is an enhanced version of the `warn!` macro from the `tracing`
+/// crate, adding automatic indentation based on [`span_depth`].
+///
+/// This macro is available only if the "tracing" feature is enabled.
+#[macro_export]
+macro_rules! itracing_warn {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::itracing::tracing::warn!(
+                    "{}",
+                    $crate::indent_text($crate::itracing::span_depth(), &$crate::format!($($t)*))
+                )
+            }
+        } else {
+            $crate::itracing::tracing::warn!("{}", $crate::format!($($t)*))
+        }}
+    }
+}
+
+/// Records an error event with a message indented by the span depth.
+///
+/// This macro is an enhanced version of the `error!` macro from the `tracing`
+/// crate, adding automatic indentation based on [`span_depth`].
+///
+/// This macro is available only if the "tracing" feature is enabled.
+#[macro_export]
+macro_rules! itracing_error {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::itracing::tracing::error!(
+                    "{}",
+                    $crate::indent_text($crate::itracing::span_depth(), &$crate::format!($($t)*))
+                )
+            }
+        } else {
+            $crate::itracing::tracing::error!("{}", $crate::format!($($t)*))
+        }}
+    }
+}
@@ -0,0 +1,94 @@
+use crate::output::{emit, Stream};
+use alloc::format;
+use core::panic::Location;
+use serde_json::Value;
+
+/// Prints a JSON value as an indented tree, aligned with the output of the
+/// calling function.
+///
+/// Every key of an object and every element of an array is printed on its
+/// own line, with array elements marked by a `-`. Scalars follow on the same
+/// line, while nested objects and arrays are printed one level deeper below
+/// it. The tree starts at the call depth of the calling function, so a dump
+/// of some configuration or state lines up with the trace around it.
+/// Strings are printed in their JSON representation, and empty objects and
+/// arrays as `{}` and `[]`.
+///
+/// It returns the number of lines printed, and like the printing macros
+/// prints nothing if the "enabled" feature is disabled.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprint_json, iprintln, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("loading");
+///     load();
+///     assert_eq!(
+///         *CAPTURED.lock().unwrap(),
+///         "loading
+///     limits:
+///         hosts:
+///             - \"a\"
+///             -
+///                 port: 80
+///         retries: 3
+///         tags: []
+///     name: \"demo\"
+/// "
+///     );
+/// }
+///
+/// fn load() {
+///     let config = serde_json::json!({
+///         "name": "demo",
+///         "limits": { "retries": 3, "hosts": ["a", { "port": 80 }], "tags": [] },
+///     });
+///     assert_eq!(iprint_json(&config), 8);
+/// }
+/// ```
+///
+/// This function is available only if the "serde_json" feature is enabled.
+#[inline(always)]
+#[track_caller]
+pub fn iprint_json(value: &Value) -> usize {
+    if cfg!(feature = "enabled") {
+        let caller = Location::caller();
+        print_value(crate::call_depth!(), value, (caller.file(), caller.line()))
+    } else {
+        0
+    }
+}
+
+fn print_value(depth: usize, value: &Value, location: (&'static str, u32)) -> usize {
+    match value {
+        Value::Object(map) if !map.is_empty() => map
+            .iter()
+            .map(|(key, value)| print_entry(depth, &format!("{}:", key), value, location))
+            .sum(),
+        Value::Array(array) if !array.is_empty() => array
+            .iter()
+            .map(|value| print_entry(depth, "-", value, location))
+            .sum(),
+        _ => emit(depth, &value.to_string(), Stream::Stdout, true, location),
+    }
+}
+
+fn print_entry(depth: usize, label: &str, value: &Value, location: (&'static str, u32)) -> usize {
+    let nested = match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(array) => !array.is_empty(),
+        _ => false,
+    };
+    if nested {
+        emit(depth, label, Stream::Stdout, true, location) + print_value(depth + 1, value, location)
+    } else {
+        let line = format!("{} {}", label, value);
+        emit(depth, &line, Stream::Stdout, true, location)
+    }
+}
@@ -12,15 +12,87 @@
 //!
 //! This library may not function correctly when compiled in release mode due
 //! to function inlining. It is recommended to use it in debug mode for accurate results.
+//! A release build prints a one-time warning to stderr when the depth is first
+//! read from the stack pointer, unless [`suppress_release_warning`] was called.
+//!
+//! The call depth is derived from the stack pointer, which is currently read on
+//! `x86_64` and `aarch64` targets. On every other architecture, or when exact
+//! results are needed in release mode, the depth can be tracked explicitly with
+//! [`scope`] guards instead. The `portable` feature approximates the stack
+//! pointer by the address of a local variable instead of reading it with inline
+//! assembly, which works on every architecture and without `unsafe` code, if a
+//! bit less precisely.
 //!
 //! ## Features
 //!
 //! - **iprintln! macro**: This is an enhanced version of `println!`, adding automatic indentation.
-//! - **iformat! macro**: Allows for custom indented formatting.
+//!   `iprintln!(@debug value)` and `iprintln!(@display value)` print a single value.
+//! - **iprintln_if! macro**: Like `iprintln!`, but only formats and prints if a condition holds.
+//! - **iseparator! macro**: Prints a horizontal rule at the current indentation.
+//! - **iprint! macro**: Like `iprintln!`, but without the trailing newline.
+//! - **ieprintln! and ieprint! macros**: The same as `iprintln!` and `iprint!`, but printing to stderr.
+//! - **iwriteln! and iwrite! macros**: The same as `iprintln!` and `iprint!`, but writing into any writer.
+//! - **idbg! macro**: An indented version of `dbg!`.
+//! - **itype! macro**: Prints and returns a value together with its type name.
+//! - **ifn! macro**: Expands to the name of the enclosing function, e.g. to label an
+//!   `itrace_scope!`.
+//! - **idump! macro**: Prints every entry of a collection on its own line, one level deeper.
+//! - **JSON trees**: `iprint_json` prints a `serde_json::Value` as an indented tree,
+//!   enabled by the `serde_json` feature.
+//! - **ipanic! macro**: Prints an indented message, then panics with it.
+//! - **panic hook**: `install_panic_hook` prints the message of any panic indented by the
+//!   call depth, before the previous hook runs.
+//! - **iassert! and iassert_eq! macros**: Assertions whose panic messages are indented.
+//! - **itrace_scope! macro**: Prints matching entry and exit banners around a scope.
+//! - **OpenTelemetry spans**: Exports every `itrace_scope!` as a nested span, enabled by the
+//!   `otel` feature.
+//! - **with_extra_indent! macro**: Indents the output of a block by extra levels.
+//! - **iformat! macro**: Allows for custom indented formatting. `iformat_cow!` avoids
+//!   allocating when no indentation is needed.
 //! - **call_depth! macro**: Provides the current depth of the function call stack,
 //!   useful for custom logging or tracing solutions.
+//! - **logical contexts**: `push_context` and `pop_context` maintain a stack shared by
+//!   all threads, by whose depth `iformat_ctx!` indents instead of the call depth.
+//! - **scope guards**: Track the depth explicitly with `iprint::scope()` for reliable
+//!   results in release mode, or let the `#[indent]` attribute of the `macros`
+//!   feature add the guard for you, or `#[indent_all]` to every method of an
+//!   `impl`. The `async` feature adds `iprint::async_scope()` guards that can be
+//!   held across `.await` points.
+//! - **timestamps**: Prefixes the printed lines with the time of day, enabled by the `time` feature.
+//! - **elapsed time**: Optionally prefixes the printed lines with the milliseconds
+//!   since the program started or `reset_clock` was called.
+//! - **thread names**: Optionally prefixes the printed lines with the name of their
+//!   thread.
+//! - **wide indent characters**: Counts the indent character by its display width,
+//!   enabled by the `unicode-width` feature.
+//! - **line wrapping**: `set_wrap_width` wraps long lines at a fixed width, keeping the
+//!   indentation, and the `terminal-size` feature at the width of the terminal.
+//! - **control character escaping**: `set_escape_control_chars` prints control characters
+//!   as escapes like `\x1b`, so binary data can't corrupt the terminal.
+//! - **source locations**: Optionally prefixes printed messages with the `file:line` of the macro call.
+//! - **colored output**: Tints the printed output by call depth, enabled by the `color` feature.
+//! - **JSON output**: `set_format(OutputFormat::Json)` prints every message as a JSON object
+//!   with its depth as a field, for machine parsing.
+//! - **output redirection**: `set_output_sink` sends everything printed to stdout
+//!   to a callback instead.
+//! - **module filtering**: `set_module_filter` silences the printing macros outside of the
+//!   given modules.
+//! - **output capturing**: `testing::capture` returns what a closure printed, for
+//!   assertions in tests, enabled by the `testing` feature.
+//! - **trace events**: `set_event_sink` reports entered and left scopes and printed
+//!   messages as structured `TraceEvent`s, e.g. for interactive trace viewers.
+//! - **recorded trees**: `start_recording` keeps the trace events instead of printing them,
+//!   and `render_tree` draws the recorded run as a tree.
+//! - **indenting writers**: `IndentWriter` and `IndentFmtWriter` wrap an `io::Write`
+//!   or `fmt::Write` and indent everything written through them.
+//! - **stdout capturing**: `capture_stdout` indents the output of code that prints
+//!   directly to stdout, enabled by the `capture` feature on unix.
 //! - **indented logging**: Offers five levels of logging (`itrace`, `idebug`, `iinfo`,
-//!   `iwarn`, `ierror`) that are feature-gated by the `log` feature.
+//!   `iwarn`, `ierror`) and `ilog` for a level chosen at runtime, feature-gated by
+//!   the `log` feature.
+//! - **indented tracing**: Offers the same five levels for the `tracing` crate
+//!   (`itracing_info` and friends), indented by span depth and feature-gated by
+//!   the `tracing` feature.
 //!
 //! ## Installation
 //!
@@ -78,6 +150,106 @@
 //! }
 //! ```
 //!
+//! ### Scope guards
+//!
+//! ```rust
+//! use iprint::iprintln;
+//!
+//! fn traced() {
+//!     // While a guard is alive, the depth is the number of alive guards.
+//!     let _guard = iprint::scope();
+//!     iprintln!("Indented by one level, even in release mode.");
+//! }
+//! ```
+//!
+//! With the `macros` feature enabled, `#[iprint::indent]` inserts the guard
+//! into a function automatically:
+//!
+//! ```rust
+//! #[cfg(feature = "macros")]
+//! {
+//!     use iprint::{indent, iprintln};
+//!
+//!     #[indent]
+//!     fn traced() {
+//!         iprintln!("Indented by one level, even in release mode.");
+//!     }
+//! }
+//! ```
+//!
+//! `#[iprint::indent_all]` does the same for every method of an `impl` block:
+//!
+//! ```rust
+//! #[cfg(feature = "macros")]
+//! {
+//!     use iprint::{indent_all, iprintln};
+//!
+//!     struct Parser;
+//!
+//!     #[indent_all]
+//!     impl Parser {
+//!         fn parse(&self) {
+//!             iprintln!("Indented by one level.");
+//!             self.token();
+//!         }
+//!
+//!         fn token(&self) {
+//!             iprintln!("Indented by two levels.");
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! ### Configuration
+//!
+//! ```rust
+//! // Indent by two spaces per level instead of four.
+//! iprint::set_indent_width(2);
+//!
+//! // Or indent by one tab per level.
+//! iprint::set_indent_str("\t");
+//!
+//! // Or make the indentation visible.
+//! iprint::set_indent_char('.');
+//!
+//! // Or draw the nesting as a tree.
+//! iprint::set_indent_style(iprint::IndentStyle::Tree);
+//!
+//! // Or mark each line with its depth, like `[L3] `.
+//! iprint::set_indent_style(iprint::IndentStyle::Marker);
+//! ```
+//!
+//! The indent width can also be fixed at build time, without any runtime
+//! configuration, by setting the `IPRINT_INDENT_WIDTH` environment variable
+//! when compiling, e.g. `IPRINT_INDENT_WIDTH=2 cargo build`.
+//!
+//! ### Disabling the macros
+//!
+//! All macros are active through the default `enabled` feature. Without it,
+//! `call_depth!` always returns 0 without reading the stack pointer, `iformat!`
+//! and the macros built on it format without indentation, and the printing
+//! macros print nothing and report 0 printed lines. This keeps the calls in the source while removing
+//! their overhead from production builds:
+//!
+//! ```toml
+//! [dependencies]
+//! iprint = { version = "0.1.4", default-features = false, features = ["std"] }
+//! ```
+//!
+//! ### `no_std`
+//!
+//! The crate depends on `std` through its default `std` feature. Without it,
+//! the crate is `#![no_std]` and only needs `alloc`, but the `critical-section`
+//! feature has to be enabled instead, and the program has to provide a
+//! [`critical-section`](https://docs.rs/critical-section) implementation. The
+//! depth is then tracked globally rather than per thread, and the printing
+//! macros are unavailable; use `iformat!` or `iwriteln!` with your own writer:
+//!
+//! ```toml
+//! [dependencies]
+//! iprint = { version = "0.1.4", default-features = false, features = ["enabled", "critical-section"] }
+//! ```
+//!
 //! ### Logging Functions (Feature-Gated)
 //!
 //! To use logging functions, make sure you have the `log` feature enabled.
@@ -93,137 +265,2087 @@
 //! }
 //! ```
 
-use std::cell::RefCell;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "critical-section")))]
+compile_error!("iprint requires either the `std` or the `critical-section` feature");
 
-thread_local!(
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+#[doc(hidden)]
+pub use alloc::format;
+
+mod sync;
+use sync::local;
+
+mod config;
+pub use config::*;
+
+mod render;
+pub use render::*;
+
+local!(
     #[doc(hidden)]
-    pub static STACK: RefCell<Vec<usize>> = RefCell::new(vec![])
+    pub static STACK: RefCell<Vec<usize>> = RefCell::new(Vec::new())
+);
+
+local!(
+    static DEPTH_OFFSET: Cell<isize> = Cell::new(0)
+);
+
+local!(
+    static ANCHOR: Cell<usize> = Cell::new(0)
+);
+
+local!(
+    static MIN_DEPTH: Cell<usize> = Cell::new(0)
 );
 
-#[doc(hidden)]
+mod guard;
+pub use guard::*;
+
+#[cfg(feature = "macros")]
+pub use iprint_macros::{indent, indent_all};
+
+#[cfg(feature = "async")]
+mod task;
+#[cfg(feature = "async")]
+pub use task::{async_scope, with_async_depth, AsyncDepthGuard};
+
+#[cfg(feature = "tracing")]
+pub mod itracing;
+
+#[cfg(feature = "color")]
+mod color;
+#[cfg(feature = "color")]
+pub use color::{color_enabled, set_color_enabled};
+
+mod output;
+pub use output::*;
+
+mod writer;
+pub use writer::*;
+
+#[cfg(all(feature = "capture", unix))]
+mod capture;
+#[cfg(all(feature = "capture", unix))]
+pub use capture::*;
+
+#[cfg(feature = "serde_json")]
+mod json;
+#[cfg(feature = "serde_json")]
+pub use json::iprint_json;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "otel")]
+pub mod otel;
+
+#[cfg(feature = "std")]
+mod context;
+#[cfg(feature = "std")]
+pub use context::*;
+
+#[cfg(feature = "std")]
+mod record;
+#[cfg(feature = "std")]
+pub use record::*;
+
+#[cfg(feature = "std")]
+mod panic;
+#[cfg(feature = "std")]
+pub use panic::install_panic_hook;
+
+#[cfg(feature = "std")]
+mod dump;
+#[cfg(feature = "std")]
+pub use dump::*;
+
+#[cfg(all(
+    not(feature = "portable"),
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! stack_ptr {
+    () => ({
+        let mut rsp: usize;
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            core::arch::asm!("mov {}, rsp", out(reg) rsp);
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            core::arch::asm!("mov {}, sp", out(reg) rsp);
+        }
+        rsp
+    })
+}
+
+#[cfg(feature = "portable")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! stack_ptr {
+    () => {
+        $crate::caller_stack_ptr()
+    };
+}
+
+/// Retrieves the current call depth of the function stack.
+///
+/// This macro returns an integer representing the depth of the function
+/// call stack at the point where it is invoked.
+///
+/// # Example
+///
+/// ```
+/// use iprint::call_depth;
+///
+/// fn main() {
+///     // Call depth here should be 0, since this is the main function.
+///     assert_eq!(call_depth!(), 0);
+///
+///     // Call first_function(), this will increase the call depth.
+///     first_function();
+///
+///     // Call depth here should be 0 again
+///     assert_eq!(call_depth!(), 0);
+/// }
+///
+/// fn first_function() {
+///     // Call depth here should be 1, since we're one function deep from main.
+///     assert_eq!(call_depth!(), 1);
+///
+///     // Call second_function(), this will increase the call depth.
+///     second_function();
+///
+///     // Call depth here should be 1 again
+///     assert_eq!(call_depth!(), 1);
+/// }
+///
+/// fn second_function() {
+///     // Call depth here should be 2, since we're two functions deep from main.
+///     assert_eq!(call_depth!(), 2);
+/// }
+/// ```
+///
+/// The depth can be shifted with [`set_depth_offset`], or rebased on the depth
+/// of a particular function with [`anchor`].
+///
+/// This is a macro because the stack pointer has to be read in the frame whose
+/// depth is wanted; see [`call_depth_here`] for a function form and its
+/// caveats.
+///
+/// # Depth tracking
+///
+/// On `x86_64` and `aarch64` the depth is detected automatically by comparing
+/// the current stack pointer against the frames seen so far on the current
+/// thread, see [`reset_depth`]. Frames of calls
+/// that returned are forgotten as soon as a shallower frame is seen, so
+/// sibling calls report the same depth even when they reuse the exact stack
+/// slot of their predecessor:
+///
+/// ```
+/// use iprint::call_depth;
+///
+/// fn main() {
+///     assert_eq!(call_depth!(), 0);
+///     for _ in 0..3 {
+///         first_sibling();
+///         second_sibling();
+///         assert_eq!(call_depth!(), 0);
+///     }
+/// }
+///
+/// #[inline(never)]
+/// fn first_sibling() {
+///     assert_eq!(call_depth!(), 1);
+///     nested();
+///     assert_eq!(call_depth!(), 1);
+/// }
+///
+/// #[inline(never)]
+/// fn second_sibling() {
+///     assert_eq!(call_depth!(), 1);
+///     nested();
+/// }
+///
+/// #[inline(never)]
+/// fn nested() {
+///     assert_eq!(call_depth!(), 2);
+/// }
+/// ```
+///
+/// Reading the depth is idempotent: calling `call_depth!` again in the same
+/// frame returns the same depth and records nothing new. Code that prints
+/// several messages in a row can still read it once and pass it on with the
+/// `depth =` argument of [`iformat!`]:
+///
+/// ```
+/// use iprint::{call_depth, iformat};
+///
+/// fn main() {
+///     call_depth!();
+///     nested();
+/// }
+///
+/// fn nested() {
+///     let depth = call_depth!();
+/// #   let frames = iprint::STACK.with(|s| s.borrow().len());
+///     assert_eq!(call_depth!(), depth);
+///     assert_eq!(call_depth!(), depth);
+/// #   assert_eq!(iprint::STACK.with(|s| s.borrow().len()), frames);
+///     let lines = [iformat!(depth = depth, "a"), iformat!(depth = depth, "b")];
+///     assert_eq!(lines, ["    a", "    b"]);
+/// }
+/// ```
+///
+/// While any [`DepthGuard`] is alive on the current thread, the depth is
+/// instead the number of alive guards and levels entered with [`indent()`],
+/// plus the async depth of the current task with the `async` feature. This is
+/// deterministic regardless of inlining, and it is the only mode available on
+/// other architectures where the stack pointer can't be read, unless the
+/// `portable` feature is enabled. Create a guard with [`scope`] at the top of
+/// every function that should be indented:
+///
+/// ```
+/// use iprint::{call_depth, scope};
+///
+/// fn traced() {
+///     let _guard = scope();
+///     assert_eq!(call_depth!(), 1);
+/// }
+/// # traced();
+/// ```
+#[macro_export]
+macro_rules! call_depth {
+    () => {
+        $crate::if_enabled! {{ $crate::offset_depth($crate::frame_depth!()) } else { 0usize }}
+    };
+}
+
+/// Returns the current call depth of the calling function.
+///
+/// This is the function form of [`call_depth!`]. The depth is derived from
+/// the stack pointer of the frame that asks for it, so it has to be read in
+/// the caller's own frame: a regular function would report its own depth,
+/// one level deeper than its caller. That's why [`call_depth!`] is a macro.
+/// This function is `#[inline(always)]`, which lets it read the stack pointer
+/// of the caller in practice, and makes it usable where a macro isn't, like
+/// in the default argument of a helper.
+///
+/// The inlining is not guaranteed, though. It never happens when the function
+/// is called through a function pointer, and the compiler may decline it in
+/// other cases, e.g. in deeply recursive code. The stack pointer is then read
+/// in a frame of its own and the depth can be off by one, so prefer
+/// [`call_depth!`] when in doubt. While
+/// any [`DepthGuard`] is alive the depth doesn't depend on the stack pointer,
+/// and both forms always agree.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{call_depth, call_depth_here};
+///
+/// fn main() {
+///     assert_eq!(call_depth!(), 0);
+///     assert_eq!(call_depth_here(), 0);
+///     report(call_depth_here());
+///
+///     // A function that isn't inlined reads the depth of its own frame.
+///     assert_eq!(not_inlined(), 1);
+/// }
+///
+/// fn report(depth: usize) {
+///     assert_eq!(depth, 0);
+/// }
+///
+/// #[inline(never)]
+/// fn not_inlined() -> usize {
+///     call_depth!()
+/// }
+/// ```
+#[inline(always)]
+pub fn call_depth_here() -> usize {
+    call_depth!()
+}
+
+/// Determines the depth of a frame at a given stack address.
+///
+/// This runs the same logic as [`call_depth!`] against a stack pointer
+/// supplied by the caller, instead of the one of the calling function:
+/// recorded frames above the address are considered returned and discarded,
+/// and the address is recorded as a new frame unless it is the innermost
+/// one already. The [anchor](anchor()) and the [offset](set_depth_offset)
+/// apply as usual, but [`DepthGuard`]s are ignored. This enables custom
+/// instrumentation, e.g. computing the depth of a captured frame.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{call_depth, call_depth_at, debug_stack, stack_grows_down};
+///
+/// fn main() {
+///     assert_eq!(call_depth!(), 0);
+///     let top = *debug_stack().last().unwrap();
+///     let deeper = |bytes: usize| {
+///         if stack_grows_down() {
+///             top - bytes
+///         } else {
+///             top + bytes
+///         }
+///     };
+///     assert_eq!(call_depth_at!(deeper(64)), 1);
+///     assert_eq!(call_depth_at!(deeper(128)), 2);
+///     assert_eq!(call_depth_at!(top), 0);
+/// }
+/// ```
+#[macro_export]
+macro_rules! call_depth_at {
+    ($stack_pointer:expr) => {
+        $crate::if_enabled! {{
+            $crate::offset_depth($crate::record_frame($stack_pointer))
+        } else {{
+            let _: usize = $stack_pointer;
+            0usize
+        }}}
+    };
+}
+
+/// Retrieves the current call depth, reading the stack pointer through a
+/// function that is never inlined.
+///
+/// This is a variant of [`call_depth!`] for release builds. Instead of
+/// reading the stack pointer with inline assembly, it calls a shim that
+/// can't be inlined and returns the stack pointer of the call site. The call
+/// forces the calling function to have set up its frame at that point, so
+/// the depth is always measured at a well-defined frame boundary. Both macros read the same stack pointer, so
+/// they agree with each other and can be mixed freely.
+///
+/// What the shim can't do is keep the calling function itself from being
+/// inlined into its own caller, which merges their frames into one depth.
+/// For reliable indentation in release builds, mark the traced functions
+/// `#[inline(never)]` as well, or track the depth explicitly with [`scope`]
+/// guards. The following test shows the pattern, and is meant to be run
+/// with `cargo test --release -- --ignored`:
+///
+/// ```ignore
+/// use iprint::{call_depth, call_depth_stable};
+/// use std::hint::black_box;
+///
+/// fn main() {
+///     iprint::suppress_release_warning();
+///     assert_eq!(call_depth_stable!(), 0);
+///
+///     // A plain function may be inlined into main in release builds, and
+///     // then reports depth 0 instead of 1.
+///     assert!(plain(1) <= 1);
+///
+///     // Functions that are never inlined keep frames of their own.
+///     assert_eq!(traced_outer(1), 2);
+/// }
+///
+/// fn plain(n: u64) -> usize {
+///     black_box(n);
+///     call_depth!()
+/// }
+///
+/// #[inline(never)]
+/// fn traced_outer(n: u64) -> usize {
+///     assert_eq!(call_depth_stable!(), 1);
+///     traced_inner(black_box(n))
+/// }
+///
+/// #[inline(never)]
+/// fn traced_inner(n: u64) -> usize {
+///     black_box(n);
+///     call_depth_stable!()
+/// }
+/// ```
+///
+/// In debug builds, both macros always report the same depth:
+///
+/// ```
+/// use iprint::{call_depth, call_depth_stable};
+///
+/// fn main() {
+///     assert_eq!(call_depth_stable!(), 0);
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(call_depth!(), 1);
+///     assert_eq!(call_depth_stable!(), 1);
+/// }
+/// ```
+#[macro_export]
+macro_rules! call_depth_stable {
+    () => {
+        $crate::if_enabled! {{ $crate::offset_depth($crate::stable_frame_depth!()) } else { 0usize }}
+    };
+}
+
+/// Returns the stack pointer of the caller at the call site.
+///
+/// A naked function is never inlined and has no frame of its own, so the
+/// call returns exactly the stack pointer that `stack_ptr!` would read in the
+/// caller, after the caller has set up its frame for the call.
+#[cfg(all(not(feature = "portable"), target_arch = "x86_64"))]
+#[doc(hidden)]
+#[unsafe(naked)]
+pub extern "C" fn caller_stack_ptr() -> usize {
+    // Skips the return address pushed by the call.
+    core::arch::naked_asm!("lea rax, [rsp + 8]", "ret")
+}
+
+/// Returns the stack pointer of the caller at the call site.
+///
+/// A naked function is never inlined and has no frame of its own, so the
+/// call returns exactly the stack pointer that `stack_ptr!` would read in the
+/// caller, after the caller has set up its frame for the call.
+#[cfg(all(not(feature = "portable"), target_arch = "aarch64"))]
+#[doc(hidden)]
+#[unsafe(naked)]
+pub extern "C" fn caller_stack_ptr() -> usize {
+    core::arch::naked_asm!("mov x0, sp", "ret")
+}
+
+/// Returns the address of a local in a frame right below the one of the
+/// caller.
+///
+/// This is the safe approximation of the stack pointer used by the
+/// "portable" feature. A function that is never inlined always places its
+/// frame at the same distance below the one of its caller, so every call from
+/// the same frame returns the same address, and calls from deeper frames
+/// return addresses further down the stack.
+#[cfg(feature = "portable")]
+#[doc(hidden)]
+#[inline(never)]
+pub fn caller_stack_ptr() -> usize {
+    let local = 0u8;
+    core::hint::black_box(&local) as *const u8 as usize
+}
+
+#[cfg(feature = "enabled")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! if_enabled {
+    ({ $($enabled:tt)* } else { $($disabled:tt)* }) => {
+        $($enabled)*
+    };
+}
+
+#[cfg(not(feature = "enabled"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! if_enabled {
+    ({ $($enabled:tt)* } else { $($disabled:tt)* }) => {
+        $($disabled)*
+    };
+}
+
+#[cfg(not(any(feature = "portable", target_arch = "x86_64", target_arch = "aarch64")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! frame_depth {
+    () => {
+        $crate::explicit_depth()
+    };
+}
+
+#[cfg(any(feature = "portable", target_arch = "x86_64", target_arch = "aarch64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! frame_depth {
+    () => {{
+        let stack_pointer = $crate::stack_ptr!();
+        let explicit_depth = $crate::explicit_depth();
+        if explicit_depth > 0 {
+            explicit_depth
+        } else {
+            $crate::record_frame(stack_pointer)
+        }
+    }};
+}
+
+#[cfg(not(any(feature = "portable", target_arch = "x86_64", target_arch = "aarch64")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! stable_frame_depth {
+    () => {
+        $crate::explicit_depth()
+    };
+}
+
+#[cfg(any(feature = "portable", target_arch = "x86_64", target_arch = "aarch64"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! stable_frame_depth {
+    () => {{
+        let stack_pointer = $crate::caller_stack_ptr();
+        let explicit_depth = $crate::explicit_depth();
+        if explicit_depth > 0 {
+            explicit_depth
+        } else {
+            $crate::record_frame(stack_pointer)
+        }
+    }};
+}
+
+/// Forgets every frame recorded on the current thread.
+///
+/// The frames are recorded per thread, so every thread starts its own
+/// indentation at zero and never affects the indentation of other threads.
+/// Calling this function rebaselines the current thread, making the next
+/// frame that asks for its depth the new depth zero. Alive [`DepthGuard`]s
+/// are not affected.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{call_depth, reset_depth};
+/// use std::thread;
+///
+/// fn worker() {
+///     reset_depth();
+///     assert_eq!(call_depth!(), 0);
+///     nested();
+///     assert_eq!(call_depth!(), 0);
+/// }
+///
+/// fn nested() {
+///     assert_eq!(call_depth!(), 1);
+/// }
+///
+/// fn main() {
+///     let handles: Vec<_> = (0..4).map(|_| thread::spawn(worker)).collect();
+///     for handle in handles {
+///         handle.join().unwrap();
+///     }
+/// }
+/// ```
+///
+/// Resetting is never needed to bound the memory used for the frames. A frame
+/// is only recorded if it is shallower than every frame recorded before it,
+/// and recording it discards every deeper frame, whose call must have returned.
+/// So there are never more recorded frames than the deepest call stack seen
+/// had, no matter how often the program recurses and returns:
+///
+/// ```
+/// use iprint::call_depth;
+///
+/// fn recurse(n: usize) {
+///     call_depth!();
+///     if n > 0 {
+///         recurse(n - 1);
+///     }
+/// }
+///
+/// # fn frames() -> usize { iprint::STACK.with(|s| s.borrow().len()) }
+/// fn main() {
+///     call_depth!();
+///     for i in 0..1000 {
+///         recurse(i % 50);
+///         // `main` and at most 50 levels of recursion.
+///         assert!(frames() <= 51);
+///         call_depth!();
+///     }
+///     assert_eq!(frames(), 1);
+/// }
+/// ```
+pub fn reset_depth() {
+    STACK.with(|c| c.borrow_mut().clear());
+    clear_anchor();
+}
+
+/// Returns the stack pointers of the frames recorded on the current thread,
+/// from the outermost to the innermost one.
+///
+/// Every frame that asked for its depth with [`call_depth!`] is recorded, and
+/// its depth is its index in this list. Frames of calls that returned are
+/// only discarded the next time a depth is determined. This is meant as a
+/// diagnostic aid for when the detected depths look wrong, e.g. to log the
+/// recorded addresses around the misbehaving calls. The list is always empty
+/// on architectures where the stack pointer can't be read, unless the
+/// `portable` feature is enabled.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{call_depth, debug_stack, stack_grows_down};
+///
+/// fn main() {
+///     call_depth!();
+///     outer();
+/// }
+///
+/// fn outer() {
+///     call_depth!();
+///     inner();
+/// }
+///
+/// fn inner() {
+///     assert_eq!(call_depth!(), 2);
+///     let stack = debug_stack();
+///     println!("{:x?}", stack);
+///     # #[cfg(any(feature = "portable", target_arch = "x86_64", target_arch = "aarch64"))]
+///     assert_eq!(stack.len(), 3);
+///     for frames in stack.windows(2) {
+///         assert_eq!(frames[1] < frames[0], stack_grows_down());
+///     }
+/// }
+/// ```
+pub fn debug_stack() -> Vec<usize> {
+    STACK.with(|c| c.borrow().clone())
+}
+
+/// Makes the depth of the calling function the new depth zero of the current
+/// thread.
+///
+/// The anchored depth is subtracted from every depth reported by
+/// [`call_depth!`] afterwards, before the [depth offset](set_depth_offset) is
+/// applied, and shallower frames report 0. Unlike a fixed offset, this
+/// captures the actual depth of the caller, which is useful when the
+/// interesting code, like the handler of a request in a server, already runs
+/// several frames deep. The anchor stays in place until it is replaced,
+/// [cleared](clear_anchor) or the depth is [reset](reset_depth).
+///
+/// # Example
+///
+/// ```
+/// use iprint::{anchor, iformat};
+///
+/// fn main() {
+///     iformat!("main");
+///     serve();
+/// }
+///
+/// fn serve() {
+///     iformat!("serve");
+///     handle();
+/// }
+///
+/// fn handle() {
+///     assert_eq!(iformat!("request"), "        request");
+///     anchor();
+///     assert_eq!(iformat!("request"), "request");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("nested"), "    nested");
+/// }
+/// ```
+#[inline(always)]
+pub fn anchor() {
+    #[cfg(feature = "enabled")]
+    {
+        let depth = frame_depth!();
+        ANCHOR.with(|a| a.set(depth));
+    }
+}
+
+/// Removes the anchor set with [`anchor`], so depths are absolute again.
+pub fn clear_anchor() {
+    ANCHOR.with(|a| a.set(0));
+}
+
+/// Runs `f` with its own code as the new depth zero, and restores the
+/// previous anchor afterwards.
+///
+/// This is the scoped form of [`anchor`]: the body of the closure starts at
+/// depth 0, the functions it calls at depth 1 and so on, while the
+/// indentation before and after the call is unaffected. It suits framework
+/// entry points that should each start their subtree at zero indentation
+/// without affecting their siblings. Calls can be nested, and the previous
+/// anchor is restored even if `f` panics.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, with_anchor};
+///
+/// fn main() {
+///     iformat!("main");
+///     serve();
+/// }
+///
+/// fn serve() {
+///     assert_eq!(iformat!("before"), "    before");
+///     with_anchor(|| {
+///         assert_eq!(iformat!("inside"), "inside");
+///         plugin();
+///     });
+///     assert_eq!(iformat!("after"), "    after");
+/// }
+///
+/// fn plugin() {
+///     assert_eq!(iformat!("plugin"), "    plugin");
+///     with_anchor(|| assert_eq!(iformat!("nested"), "nested"));
+///     assert_eq!(iformat!("plugin"), "    plugin");
+/// }
+/// ```
+///
+/// With [depth guards](crate::scope), the depth of the caller becomes depth
+/// zero, since the closure doesn't enter a guard of its own:
+///
+/// ```
+/// use iprint::{iformat, scope, with_anchor};
+///
+/// fn main() {
+///     serve();
+/// }
+///
+/// fn serve() {
+///     let _g = scope();
+///     assert_eq!(iformat!("before"), "    before");
+///     with_anchor(|| {
+///         assert_eq!(iformat!("inside"), "inside");
+///         plugin();
+///     });
+///     assert_eq!(iformat!("after"), "    after");
+/// }
+///
+/// fn plugin() {
+///     let _g = scope();
+///     assert_eq!(iformat!("plugin"), "    plugin");
+///     with_anchor(|| assert_eq!(iformat!("nested"), "nested"));
+///     assert_eq!(iformat!("plugin"), "    plugin");
+/// }
+/// ```
+#[inline(always)]
+pub fn with_anchor<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "enabled")]
+    {
+        struct Restore(usize);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                ANCHOR.with(|a| a.set(self.0));
+            }
+        }
+
+        // Without guards, the closure runs in a frame of its own, one level
+        // below the caller.
+        let explicit_depth = explicit_depth();
+        let depth = if explicit_depth > 0 {
+            explicit_depth
+        } else {
+            frame_depth!() + 1
+        };
+        let _restore = Restore(ANCHOR.with(|a| a.replace(depth)));
+        f()
+    }
+    #[cfg(not(feature = "enabled"))]
+    f()
+}
+
+/// Sets an offset that is added to the call depth of the current thread.
+///
+/// The offset is applied to every depth reported by [`call_depth!`], so it
+/// also shifts the indentation. The result is clamped at 0. This allows
+/// wrappers around the macros to hide the frames they introduce themselves.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{depth_offset, iformat, set_depth_offset};
+///
+/// // A helper that adds a frame of its own in between the caller and iformat!.
+/// fn trace(msg: &str) -> String {
+///     let previous = depth_offset();
+///     set_depth_offset(previous - 1);
+///     let line = iformat!("{}", msg);
+///     set_depth_offset(previous);
+///     line
+/// }
+///
+/// fn main() {
+///     assert_eq!(iformat!("top"), "top");
+///     assert_eq!(trace("top"), "top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("nested"), "    nested");
+///     assert_eq!(trace("nested"), "    nested");
+/// }
+/// ```
+pub fn set_depth_offset(offset: isize) {
+    DEPTH_OFFSET.with(|o| o.set(offset));
+}
+
+/// Returns the offset that is added to the call depth of the current thread.
+pub fn depth_offset() -> isize {
+    DEPTH_OFFSET.with(|o| o.get())
+}
+
+/// Sets the smallest call depth reported on the current thread.
+///
+/// [`call_depth!`] reports the greater of the actual depth and `min_depth`,
+/// after applying the [`anchor`] and the [offset](set_depth_offset), so
+/// everything printed by the thread is indented by at least `min_depth`
+/// levels. This lets e.g. each plugin of a host render below the host's own
+/// output, even at the top of its call chain. It defaults to 0.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_min_depth};
+///
+/// fn main() {
+///     set_min_depth(2);
+///     assert_eq!(iformat!("top"), "        top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     iformat!("nested");
+///     deeper();
+/// }
+///
+/// fn deeper() {
+///     assert_eq!(iformat!("deeper"), "        deeper");
+///     deepest();
+/// }
+///
+/// fn deepest() {
+///     assert_eq!(iformat!("deepest"), "            deepest");
+/// }
+/// ```
+pub fn set_min_depth(min_depth: usize) {
+    MIN_DEPTH.with(|m| m.set(min_depth));
+}
+
+/// Returns the smallest call depth reported on the current thread.
+pub fn min_depth() -> usize {
+    MIN_DEPTH.with(|m| m.get())
+}
+
+#[doc(hidden)]
+pub fn offset_depth(depth: usize) -> usize {
+    let anchor = ANCHOR.with(|a| a.get());
+    depth
+        .saturating_sub(anchor)
+        .saturating_add_signed(depth_offset())
+        .max(min_depth())
+}
+
+/// Returns whether the stack grows toward lower addresses.
+///
+/// This is the case on almost every platform, but the direction is probed
+/// once at runtime by comparing the addresses of locals in two nested frames,
+/// so that [`call_depth!`] also works where the stack grows upward.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{call_depth, stack_grows_down};
+///
+/// fn main() {
+///     println!("stack grows down: {}", stack_grows_down());
+///     assert_eq!(call_depth!(), 0);
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(call_depth!(), 1);
+/// }
+/// ```
+pub fn stack_grows_down() -> bool {
+    match STACK_DIRECTION.load(Ordering::Relaxed) {
+        DIRECTION_DOWN => true,
+        DIRECTION_UP => false,
+        _ => {
+            let local = 0u8;
+            let grows_down =
+                probe_stack_direction(core::hint::black_box(&local) as *const u8 as usize);
+            let direction = if grows_down {
+                DIRECTION_DOWN
+            } else {
+                DIRECTION_UP
+            };
+            STACK_DIRECTION.store(direction, Ordering::Relaxed);
+            grows_down
+        }
+    }
+}
+
+const DIRECTION_UNKNOWN: u8 = 0;
+const DIRECTION_DOWN: u8 = 1;
+const DIRECTION_UP: u8 = 2;
+
+static STACK_DIRECTION: AtomicU8 = AtomicU8::new(DIRECTION_UNKNOWN);
+
+#[inline(never)]
+fn probe_stack_direction(caller_local: usize) -> bool {
+    let local = 0u8;
+    (core::hint::black_box(&local) as *const u8 as usize) < caller_local
+}
+
+static MIN_FRAME_DELTA: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the distance in bytes that the stack pointer of a frame must have
+/// from the last recorded frame to count as one level deeper.
+///
+/// In partially optimized builds, small helpers that got inlined into their
+/// caller can still leave a stack pointer a few bytes away from it, which
+/// [`call_depth!`] would count as another level. A frame closer to the last
+/// recorded one than `delta` bytes is merged into it and reports the same
+/// depth instead. Frames of real calls are usually at least a few dozen bytes
+/// apart, so a small delta like 48 merges the spurious levels only. It
+/// defaults to 0, which counts every distinct stack pointer.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, set_min_frame_delta};
+///
+/// fn main() {
+///     // Far beyond the size of any frame, which merges all of them.
+///     set_min_frame_delta(1 << 20);
+///     iformat!("top");
+///     assert_eq!(nested(), "nested");
+///
+///     set_min_frame_delta(0);
+///     assert_eq!(nested(), "    nested");
+/// }
+///
+/// fn nested() -> String {
+///     iformat!("nested")
+/// }
+/// ```
+pub fn set_min_frame_delta(delta: usize) {
+    MIN_FRAME_DELTA.store(delta, Ordering::Relaxed);
+}
+
+/// Returns the distance in bytes that the stack pointer of a frame must have
+/// from the last recorded frame to count as one level deeper.
+pub fn min_frame_delta() -> usize {
+    MIN_FRAME_DELTA.load(Ordering::Relaxed)
+}
+
+/// Records a frame at `stack_pointer` and returns its depth.
+///
+/// `STACK` holds the stack pointers of the frames seen so far, from the
+/// outermost to the innermost. Every recorded frame deeper than
+/// `stack_pointer`, that is below it if the stack grows downward and above it
+/// otherwise, belongs to a call that already returned and is discarded first.
+/// A frame at exactly the same address is the same depth, either because it is
+/// the same frame or because a sibling call reused the slot of one that
+/// returned, so it is not pushed a second time. Neither is a frame closer to
+/// the innermost one than the [minimum frame delta](set_min_frame_delta).
+#[doc(hidden)]
+pub fn record_frame(stack_pointer: usize) -> usize {
+    #[cfg(feature = "std")]
+    if !cfg!(debug_assertions) {
+        warn_release_once();
+    }
+    let grows_down = stack_grows_down();
+    STACK.with(|c| {
+        let mut stack = c.borrow_mut();
+        while let Some(&last) = stack.last() {
+            let returned = if grows_down {
+                last < stack_pointer
+            } else {
+                last > stack_pointer
+            };
+            if returned {
+                stack.pop();
+            } else {
+                break;
+            }
+        }
+        let merged = stack
+            .last()
+            .is_some_and(|&last| last.abs_diff(stack_pointer) < min_frame_delta());
+        if stack.last() != Some(&stack_pointer) && !merged {
+            stack.push(stack_pointer);
+        }
+        stack.len() - 1
+    })
+}
+
+#[cfg(feature = "std")]
+static RELEASE_WARNING_DONE: core::sync::atomic::AtomicBool =
+    core::sync::atomic::AtomicBool::new(false);
+
+/// Prints a warning about the reliability of release builds, the first time
+/// it is called in the process.
+#[cfg(feature = "std")]
+fn warn_release_once() {
+    if !RELEASE_WARNING_DONE.load(Ordering::Relaxed)
+        && !RELEASE_WARNING_DONE.swap(true, Ordering::Relaxed)
+    {
+        std::eprintln!(
+            "warning: iprint derives the call depth from the stack pointer, which is \
+             unreliable in release builds because of inlining; use iprint::scope() \
+             guards for exact indentation, or call iprint::suppress_release_warning() \
+             to silence this warning"
+        );
+    }
+}
+
+/// Silences the warning printed when the call depth is derived from the stack
+/// pointer in a release build.
+///
+/// Without debug assertions, the compiler inlines functions freely, so the
+/// frames that [`call_depth!`] counts may not match the functions in the
+/// source. When the depth is first read from the stack pointer in such a
+/// build, a one-time warning is printed to stderr. It is not printed while
+/// [`DepthGuard`]s are alive, since the depth is exact then.
+///
+/// # Example
+///
+/// ```
+/// // The indentation of this program is known to be good enough.
+/// iprint::suppress_release_warning();
+/// iprint::iprintln!("no warning");
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn suppress_release_warning() {
+    RELEASE_WARNING_DONE.store(true, Ordering::Relaxed);
+}
+
+/// Formats a given string with indentation based on the current call depth.
+///
+/// This macro works similarly to Rust's built-in `format!` macro,
+/// but prepends an indentation to the formatted string. The level of
+/// indentation is determined by the current call depth in the stack, and
+/// each level is [`indent_width`] spaces wide unless a custom string was set
+/// with [`set_indent_str`] or another [`IndentStyle`] was chosen.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iformat;
+///
+/// fn my_function() {
+///     let msg = iformat!("This will be indented based on call depth.");
+///     println!("{}", msg);
+/// }
+/// ```
+///
+/// Every line of a multi-line string is indented. Like [`str::lines`], a
+/// single trailing newline doesn't start another line, but empty lines before
+/// it do, and `\r\n` line endings become `\n` unless
+/// [`set_preserve_line_endings`] is enabled:
+///
+/// ```
+/// use iprint::iformat;
+///
+/// fn main() {
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("a\nb"), "    a\n    b");
+///     assert_eq!(iformat!("a\n"), "    a");
+///     assert_eq!(iformat!("a\n\n"), "    a\n    ");
+/// }
+/// ```
+///
+/// A leading `depth = N` argument replaces the call depth, which is useful to
+/// indent output by the depth of a data structure rather than of the stack:
+///
+/// ```
+/// use iprint::iformat;
+///
+/// struct Node {
+///     name: &'static str,
+///     children: Vec<Node>,
+/// }
+///
+/// fn render(node: &Node, depth: usize, out: &mut Vec<String>) {
+///     out.push(iformat!(depth = depth, "{}", node.name));
+///     for child in &node.children {
+///         render(child, depth + 1, out);
+///     }
+/// }
+///
+/// let tree = Node {
+///     name: "root",
+///     children: vec![Node { name: "leaf", children: vec![] }],
+/// };
+/// let mut out = Vec::new();
+/// render(&tree, 0, &mut out);
+/// assert_eq!(out, ["root", "    leaf"]);
+/// ```
+///
+/// Leading `width = N` and `char = C` arguments, in this order, replace the
+/// configured width and character of a level for this call only, and take
+/// the place of an [indent string](set_indent_str) in the spaces style:
+///
+/// ```
+/// use iprint::{iformat, set_indent_width};
+///
+/// fn main() {
+///     set_indent_width(2);
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat!("x"), "  x");
+///     assert_eq!(iformat!(width = 8, "x"), "        x");
+///     assert_eq!(iformat!(char = '.', "x"), "..x");
+///     assert_eq!(iformat!(width = 3, char = '-', "{}", 1), "---1");
+/// }
+/// ```
+#[macro_export]
+macro_rules! iformat {
+    (width = $width:expr, char = $fill:expr, $($t:tt)+) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            let text = $crate::format!($($t)+);
+            $crate::indent_text_with(call_depth, &text, ::core::option::Option::Some(($width, $fill)))
+        }} else {{
+            let _: (usize, char) = ($width, $fill);
+            $crate::format!($($t)+)
+        }}}
+    };
+    (width = $width:expr, $($t:tt)+) => {
+        $crate::iformat!(width = $width, char = $crate::indent_char(), $($t)+)
+    };
+    (char = $fill:expr, $($t:tt)+) => {
+        $crate::iformat!(width = $crate::indent_width(), char = $fill, $($t)+)
+    };
+    (depth = $depth:expr, $($t:tt)+) => {
+        $crate::if_enabled! {{{
+            let depth: usize = $depth;
+            let text = $crate::format!($($t)+);
+            $crate::indent_text(depth, &text)
+        }} else {{
+            let _: usize = $depth;
+            $crate::format!($($t)+)
+        }}}
+    };
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            let text = $crate::format!($($t)*);
+            $crate::indent_text(call_depth, &text)
+        }} else {
+            $crate::format!($($t)*)
+        }}
+    };
+}
+
+/// Formats a given string with indentation based on the current call depth,
+/// borrowing it if no indentation is needed.
+///
+/// This macro produces the same text as [`iformat!`], but returns a
+/// [`Cow<str>`](alloc::borrow::Cow). A message without format arguments that
+/// consists of a single line and gets no indentation, e.g. at depth 0, is
+/// returned as `Cow::Borrowed` without any allocation, which makes the common
+/// top-level case of shallow traces free.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iformat_cow;
+/// use std::alloc::{GlobalAlloc, Layout, System};
+/// use std::borrow::Cow;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// struct Counting;
+///
+/// static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+///
+/// unsafe impl GlobalAlloc for Counting {
+///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+///         ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+///         System.alloc(layout)
+///     }
+///
+///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+///         System.dealloc(ptr, layout)
+///     }
+/// }
+///
+/// #[global_allocator]
+/// static GLOBAL: Counting = Counting;
+///
+/// fn main() {
+///     // The first call records the frame of main.
+///     iformat_cow!("warm up");
+///     let before = ALLOCATIONS.load(Ordering::Relaxed);
+///     let line = iformat_cow!("top");
+///     assert_eq!(ALLOCATIONS.load(Ordering::Relaxed), before);
+///     assert!(matches!(line, Cow::Borrowed("top")));
+///     nested();
+/// }
+///
+/// fn nested() {
+///     assert_eq!(iformat_cow!("nested {}", 1), "    nested 1");
+/// }
+/// ```
+#[macro_export]
+macro_rules! iformat_cow {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            $crate::indent_cow(call_depth, ::core::format_args!($($t)*))
+        }} else {
+            $crate::unindented_cow(::core::format_args!($($t)*))
+        }}
+    };
+}
+
+/// Formats a given string with indentation based on the current call depth,
+/// and returns an iterator over its indented lines.
+///
+/// The iterator yields the same lines that [`iformat!`] would join, each
+/// already indented, without building the joined string. This allows
+/// processing the lines one at a time, e.g. to feed a paginated viewer or a
+/// custom renderer.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, iformat_lines};
+///
+/// fn main() {
+///     iformat!("top");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     let lines: Vec<String> = iformat_lines!("a\nb\r\n\nc\n").collect();
+///     assert_eq!(lines, ["    a", "    b", "    ", "    c"]);
+///     assert_eq!(lines.join("\n"), iformat!("a\nb\r\n\nc\n"));
+/// }
+/// ```
+#[macro_export]
+macro_rules! iformat_lines {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            $crate::IndentedLines::new(call_depth, $crate::format!($($t)*))
+        }} else {
+            $crate::IndentedLines::unindented($crate::format!($($t)*))
+        }}
+    };
+}
+
+/// Appends a given string with indentation based on the current call depth to
+/// an existing `String`.
+///
+/// This macro produces the same text as [`iformat!`], but writes it straight
+/// onto the end of `buf`, indenting it while it is formatted, instead of
+/// allocating a new `String`. One buffer can be reused across many calls
+/// without allocating once it has grown large enough.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, iformat_to};
+///
+/// fn main() {
+///     let mut buf = String::new();
+///     iformat_to!(&mut buf, "top");
+///     buf.push('\n');
+///     nested(&mut buf);
+///     assert_eq!(buf, "top\n    a\n    b");
+/// }
+///
+/// fn nested(buf: &mut String) {
+///     iformat_to!(buf, "a\nb");
+///
+///     let mut line = String::new();
+///     iformat_to!(&mut line, "{}\r\n{}\n", "c", 'd');
+///     assert_eq!(line, iformat!("c\r\nd\n"));
+/// }
+/// ```
+#[macro_export]
+macro_rules! iformat_to {
+    ($buf:expr, $($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            $crate::indent_args_into(call_depth, ::core::format_args!($($t)*), $buf)
+        }} else {
+            $crate::unindented_args_into(::core::format_args!($($t)*), $buf)
+        }}
+    }
+}
+
+/// Prints a given string with automatic indentation to the console.
+///
+/// This macro is an enhanced version of Rust's `println!` macro,
+/// adding automatic indentation based on the current call depth.
+/// It returns the number of lines printed. Like with [`iformat!`], a trailing
+/// line break of the message doesn't start another line.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iprintln;
+///
+/// fn another_function() {
+///     iprintln!("This is like println! but with automatic indentation.");
+/// }
+///
+/// fn main() {
+///     assert_eq!(iprintln!("first\nsecond"), 2);
+///     assert_eq!(iprintln!("a\n"), 1);
+///     assert_eq!(iprintln!(""), 1);
+/// }
+/// ```
+///
+/// A message is written while holding the lock of stdout, so the lines of
+/// messages printed by several threads at once never interleave:
+///
+/// ```
+/// use iprint::iprintln;
+/// use std::process::Command;
+/// use std::thread;
+///
+/// fn main() {
+///     if std::env::var_os("IPRINT_CHILD").is_some() {
+///         let threads: Vec<_> = (0..8)
+///             .map(|t| thread::spawn(move || {
+///                 for _ in 0..100 {
+///                     iprintln!("{t} start\n{t} middle\n{t} end");
+///                 }
+///             }))
+///             .collect();
+///         for thread in threads {
+///             thread.join().unwrap();
+///         }
+///         return;
+///     }
+///
+///     // Runs this example again, to read what it writes to stdout.
+///     let output = Command::new(std::env::current_exe().unwrap())
+///         .env("IPRINT_CHILD", "1")
+///         .output()
+///         .unwrap();
+///     let stdout = String::from_utf8(output.stdout).unwrap();
+///     let lines: Vec<&str> = stdout.lines().collect();
+///     assert_eq!(lines.len(), 8 * 100 * 3);
+///     for message in lines.chunks(3) {
+///         let t = message[0].strip_suffix(" start").unwrap();
+///         assert_eq!(message, [format!("{t} start"), format!("{t} middle"), format!("{t} end")]);
+///     }
+/// }
+/// ```
+///
+/// A single value can be printed with its `Debug` or `Display`
+/// implementation without writing a format string, by prefixing it with
+/// `@debug` or `@display`:
+///
+/// ```
+/// use iprint::{iprintln, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// #[derive(Debug)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!(@display "top");
+///     nested();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "top\n    Point { x: 1, y: 2 }\n    3\n");
+/// }
+///
+/// fn nested() {
+///     iprintln!(@debug Point { x: 1, y: 2 });
+///     iprintln!(@display 1 + 2);
+/// }
+/// ```
+///
+/// Like with [`iformat!`], leading `width = N` and `char = C` arguments
+/// override the configured indentation for this call only:
+///
+/// ```
+/// use iprint::{iprintln, set_indent_width, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     set_indent_width(2);
+///     iprintln!("top");
+///     nested();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "top\n        x\n..y\n");
+/// }
+///
+/// fn nested() {
+///     iprintln!(width = 8, "x");
+///     iprintln!(char = '.', "y");
+/// }
+/// ```
+#[macro_export]
+macro_rules! iprintln {
+    (width = $width:expr, char = $fill:expr, $($t:tt)+) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                let message = $crate::format!($($t)+);
+                let unit = ::core::option::Option::Some(($width, $fill));
+                $crate::emit_with(call_depth, &message, $crate::Stream::Stdout, true, (file!(), line!()), unit)
+            } else {
+                0usize
+            }
+        }} else {{
+            let _: (usize, char) = ($width, $fill);
+            if false {
+                let _ = ::core::format_args!($($t)+);
+            }
+            0usize
+        }}}
+    };
+    (width = $width:expr, $($t:tt)+) => {
+        $crate::iprintln!(width = $width, char = $crate::indent_char(), $($t)+)
+    };
+    (char = $fill:expr, $($t:tt)+) => {
+        $crate::iprintln!(width = $crate::indent_width(), char = $fill, $($t)+)
+    };
+    (@debug $value:expr $(,)?) => {
+        $crate::iprintln!("{:?}", $value)
+    };
+    (@display $value:expr $(,)?) => {
+        $crate::iprintln!("{}", $value)
+    };
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::emit(call_depth, &$crate::format!($($t)*), $crate::Stream::Stdout, true, (file!(), line!()))
+            } else {
+                0usize
+            }
+        }} else {{
+            if false {
+                let _ = ::core::format_args!($($t)*);
+            }
+            0usize
+        }}}
+    }
+}
+
+/// Prints a given string with automatic indentation to the console if a
+/// condition holds.
+///
+/// The condition is evaluated first, and only if it is `true` is the message
+/// formatted and printed like with [`iprintln!`], so neither the format
+/// arguments nor the depth detection cost anything otherwise. This is handy
+/// for sampling output in hot loops. It returns the number of lines printed,
+/// which is 0 if the condition doesn't hold.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iprintln_if;
+///
+/// fn main() {
+///     let mut formatted = 0;
+///     let mut describe = |i: u32| {
+///         formatted += 1;
+///         i
+///     };
+///
+///     let mut printed = 0;
+///     for i in 0..1000 {
+///         printed += iprintln_if!(i % 100 == 0, "iteration {}", describe(i));
+///     }
+///     assert_eq!(printed, 10);
+///     assert_eq!(formatted, 10);
+/// }
+/// ```
+#[macro_export]
+macro_rules! iprintln_if {
+    ($cond:expr, $($t:tt)*) => {
+        $crate::if_enabled! {{
+            if $cond {
+                $crate::iprintln!($($t)*)
+            } else {
+                0usize
+            }
+        } else {{
+            if false {
+                let _: bool = $cond;
+                let _ = ::core::format_args!($($t)*);
+            }
+            0usize
+        }}}
+    };
+}
+
+/// Prints a horizontal rule of dashes at the current indentation.
+///
+/// The rule is 40 dashes wide unless a width is given, and is printed like a
+/// message of [`iprintln!`], so it lines up with the output around it.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, iseparator, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("top");
+///     nested();
+///     assert_eq!(
+///         *CAPTURED.lock().unwrap(),
+///         format!("top\n    {}\n    -----\n", "-".repeat(40))
+///     );
+/// }
+///
+/// fn nested() {
+///     iseparator!();
+///     iseparator!(5);
+/// }
+/// ```
+#[macro_export]
+macro_rules! iseparator {
+    () => {
+        $crate::iseparator!(40)
+    };
+    ($width:expr) => {{
+        $crate::iprintln!("{:-<1$}", "", $width);
+    }};
+}
+
+/// Prints a given string with automatic indentation to the console, without a
+/// trailing newline.
+///
+/// This macro is the indented counterpart of Rust's `print!` macro. Only the
+/// text passed to it is indented, so subsequent output continues on the same line.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iprint;
+///
+/// fn progress() {
+///     iprint!("working");
+///     iprint!("...");
+///     println!(" done");
+/// }
+/// ```
+#[macro_export]
+macro_rules! iprint {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::emit(call_depth, &$crate::format!($($t)*), $crate::Stream::Stdout, false, (file!(), line!()));
+            }
+        }} else {
+            if false {
+                let _ = ::core::format_args!($($t)*);
+            }
+        }}
+    }
+}
+
+/// Prints a given string with automatic indentation to the standard error.
+///
+/// This macro is the indented counterpart of Rust's `eprintln!` macro, which
+/// keeps traces separate from the regular output of a program. It returns the
+/// number of lines printed.
+///
+/// # Example
+///
+/// ```
+/// use iprint::ieprintln;
+///
+/// fn another_function() {
+///     ieprintln!("This goes to stderr with automatic indentation.");
+/// }
+/// ```
+#[macro_export]
+macro_rules! ieprintln {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::emit(call_depth, &$crate::format!($($t)*), $crate::Stream::Stderr, true, (file!(), line!()))
+            } else {
+                0usize
+            }
+        }} else {{
+            if false {
+                let _ = ::core::format_args!($($t)*);
+            }
+            0usize
+        }}}
+    }
+}
+
+/// Prints a given string with automatic indentation to the standard error,
+/// without a trailing newline.
+///
+/// This macro is the indented counterpart of Rust's `eprint!` macro.
+///
+/// # Example
+///
+/// ```
+/// use iprint::ieprint;
+///
+/// fn progress() {
+///     ieprint!("working");
+///     eprintln!(" done");
+/// }
+/// ```
+#[macro_export]
+macro_rules! ieprint {
+    ($($t:tt)*) => {
+        $crate::if_enabled! {{{
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::emit(call_depth, &$crate::format!($($t)*), $crate::Stream::Stderr, false, (file!(), line!()));
+            }
+        }} else {
+            if false {
+                let _ = ::core::format_args!($($t)*);
+            }
+        }}
+    }
+}
+
+/// Prints and returns the value of a given expression with automatic indentation.
+///
+/// This macro is the indented counterpart of Rust's `dbg!` macro. It prints
+/// the file, line and column of the invocation together with the expression
+/// and its pretty-printed `Debug` value to stderr, and then returns the value.
+/// Every line of the output is indented based on the current call depth.
+///
+/// # Example
+///
+/// ```
+/// use iprint::idbg;
+///
+/// fn compute() -> u32 {
+///     6 * 7
+/// }
+///
+/// fn main() {
+///     let answer = idbg!(compute());
+///     assert_eq!(answer, 42);
+///
+///     // Multiple values are returned as a tuple.
+///     let (a, b) = idbg!(1, "two");
+///     assert_eq!((a, b), (1, "two"));
+///
+///     // Without arguments, only the location is printed.
+///     idbg!();
+/// }
+/// ```
+#[macro_export]
+macro_rules! idbg {
+    () => {{
+        $crate::ieprintln!("[{}:{}:{}]", file!(), line!(), column!());
+    }};
+    ($val:expr $(,)?) => {
+        match $val {
+            tmp => {
+                $crate::ieprintln!(
+                    "[{}:{}:{}] {} = {:#?}",
+                    file!(),
+                    line!(),
+                    column!(),
+                    stringify!($val),
+                    &tmp
+                );
+                tmp
+            }
+        }
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($crate::idbg!($val)),+,)
+    };
+}
+
+/// Prints and returns the value of a given expression together with its
+/// type name, with automatic indentation.
+///
+/// The line, like `alloc::vec::Vec<u8> = [1]`, shows the
+/// [`type_name`](core::any::type_name) of the value and its `Debug`
+/// representation, and is printed to stdout like [`iprintln!`]. This helps
+/// to find out what a generic or an inferred type resolved to. Since the
+/// value is returned, the macro can wrap any expression inline.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, itype, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("top");
+///     nested();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "top\n    alloc::vec::Vec<u8> = [1]\n");
+/// }
+///
+/// fn nested() {
+///     let v = itype!(vec![1u8]);
+///     assert_eq!(v, [1]);
+/// }
+/// ```
 #[macro_export]
-macro_rules! stack_ptr {
-    () => ({
-        let mut rsp: usize;
-        unsafe {
-            core::arch::asm!("mov {}, rsp", out(reg) rsp);
+macro_rules! itype {
+    ($val:expr $(,)?) => {
+        match $val {
+            tmp => {
+                $crate::iprintln!("{} = {:?}", ::core::any::type_name_of_val(&tmp), &tmp);
+                tmp
+            }
         }
-        rsp
-    })
+    };
 }
 
-/// Retrieves the current call depth of the function stack.
+/// Expands to the name of the function it is used in.
 ///
-/// This macro returns an integer representing the depth of the function
-/// call stack at the point where it is invoked.
+/// The name is the last segment of the path of the enclosing function, like
+/// `parse`, which is found through the [`type_name`](core::any::type_name) of
+/// a local item, and skips any closures or async blocks the macro is used in.
+/// Passed to [`itrace_scope!`](crate::itrace_scope), it labels the entry and
+/// exit banners of a function automatically.
 ///
 /// # Example
 ///
 /// ```
-/// use iprint::call_depth;
+/// use iprint::{ifn, itrace_scope, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
 ///
 /// fn main() {
-///     // Call depth here should be 0, since this is the main function.
-///     assert_eq!(call_depth!(), 0);
+///     assert_eq!(ifn!(), "main");
+///     let in_closure = || ifn!();
+///     assert_eq!(in_closure(), "main");
 ///
-///     // Call first_function(), this will increase the call depth.
-///     first_function();
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     parse();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "-> parse\n<- parse\n");
+/// }
 ///
-///     // Call depth here should be 0 again
-///     assert_eq!(call_depth!(), 0);
+/// fn parse() {
+///     let _scope = itrace_scope!(ifn!());
 /// }
+/// ```
+#[macro_export]
+macro_rules! ifn {
+    () => {{
+        fn f() {}
+        $crate::function_name(::core::any::type_name_of_val(&f))
+    }};
+}
+
+#[doc(hidden)]
+pub fn function_name(local_item: &'static str) -> &'static str {
+    let mut path = local_item.strip_suffix("::f").unwrap_or(local_item);
+    while let Some(outer) = path.strip_suffix("::{{closure}}") {
+        path = outer;
+    }
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+/// Prints an indented message like [`iprintln!`], then panics with it.
 ///
-/// fn first_function() {
-///     // Call depth here should be 1, since we're one function deep from main.
-///     assert_eq!(call_depth!(), 1);
+/// The message is written to stdout, or the [output sink](set_output_sink),
+/// so it shows up aligned with the trace around it, and the panic payload is
+/// the same message without indentation. It accepts the same arguments as
+/// `panic!`. When the "enabled" feature is disabled, it only panics.
 ///
-///     // Call second_function(), this will increase the call depth.
-///     second_function();
+/// # Example
 ///
-///     // Call depth here should be 1 again
-///     assert_eq!(call_depth!(), 1);
+/// ```
+/// use iprint::{ipanic, iprintln, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("top");
+///     let err = std::panic::catch_unwind(|| fail(7)).unwrap_err();
+///     assert_eq!(err.downcast_ref::<String>().unwrap(), "bad state 7");
+///     assert_eq!(*CAPTURED.lock().unwrap(), "top\n    bad state 7\n");
 /// }
 ///
-/// fn second_function() {
-///     // Call depth here should be 2, since we're two functions deep from main.
-///     assert_eq!(call_depth!(), 2);
+/// fn fail(state: u32) {
+///     ipanic!("bad state {}", state);
 /// }
 /// ```
 #[macro_export]
-macro_rules! call_depth {
-    () => {{
-        let stack_pointer = $crate::stack_ptr!();
-        $crate::STACK.with(|c| {
-            let mut stack = c.borrow_mut();
-            while let Some(&last) = stack.last() {
-                if last < stack_pointer {
-                    stack.pop();
-                } else {
-                    break;
+macro_rules! ipanic {
+    () => {
+        $crate::ipanic!("explicit panic")
+    };
+    ($($t:tt)+) => {{
+        let message = $crate::format!($($t)+);
+        $crate::if_enabled! {{
+            let call_depth = $crate::call_depth!();
+            if $crate::module_enabled(::core::module_path!()) {
+                $crate::emit(call_depth, &message, $crate::Stream::Stdout, true, (file!(), line!()));
+            }
+        } else {}}
+        ::core::panic!("{}", message)
+    }};
+}
+
+/// Asserts that a boolean expression is `true`, panicking with an indented
+/// message otherwise.
+///
+/// This macro is the indented counterpart of Rust's `assert!` macro. The
+/// panic message, either the default one or the custom one, is indented
+/// based on the current call depth, so it lines up with the surrounding
+/// output. Nothing beyond the condition is evaluated when it holds.
+///
+/// Unlike the printing macros, assertions are still checked when the
+/// "enabled" feature is disabled; only the indentation is dropped.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iassert, iprintln};
+///
+/// fn check(x: i32) {
+///     iassert!(x > 0, "x must be positive, got {}", x);
+/// }
+///
+/// fn main() {
+///     iprintln!("checking");
+///     check(1);
+///
+///     let err = std::panic::catch_unwind(|| check(-1)).unwrap_err();
+///     let message = err.downcast_ref::<String>().unwrap();
+///     assert_eq!(message, "    x must be positive, got -1");
+/// }
+/// ```
+#[macro_export]
+macro_rules! iassert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            ::core::panic!("{}", $crate::iformat!("assertion failed: {}", ::core::stringify!($cond)))
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            ::core::panic!("{}", $crate::iformat!($($arg)+))
+        }
+    };
+}
+
+/// Asserts that two expressions are equal, panicking with an indented
+/// message otherwise.
+///
+/// This macro is the indented counterpart of Rust's `assert_eq!` macro. On
+/// failure, every line of the panic message, including the `Debug`
+/// representations of both values, is indented based on the current call
+/// depth. Nothing beyond the two operands is evaluated when they are equal.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iassert_eq, iprintln};
+///
+/// fn check(a: u32, b: u32) {
+///     iassert_eq!(a, b, "mismatch");
+/// }
+///
+/// fn main() {
+///     iprintln!("checking");
+///     check(2, 2);
+///
+///     let err = std::panic::catch_unwind(|| check(1, 2)).unwrap_err();
+///     let message = err.downcast_ref::<String>().unwrap();
+///     assert_eq!(
+///         message,
+///         "    assertion `left == right` failed: mismatch\n      left: 1\n     right: 2"
+///     );
+/// }
+/// ```
+#[macro_export]
+macro_rules! iassert_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    ::core::panic!(
+                        "{}",
+                        $crate::iformat!(
+                            "assertion `left == right` failed\n  left: {:?}\n right: {:?}",
+                            left_val,
+                            right_val
+                        )
+                    )
                 }
             }
-            if stack.last() != Some(&stack_pointer) {
-                stack.push(stack_pointer);
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    ::core::panic!(
+                        "{}",
+                        $crate::iformat!(
+                            "assertion `left == right` failed: {}\n  left: {:?}\n right: {:?}",
+                            $crate::format!($($arg)+),
+                            left_val,
+                            right_val
+                        )
+                    )
+                }
             }
-            stack.len() - 1
-        })
-    }};
+        }
+    };
 }
 
-/// Formats a given string with indentation based on the current call depth.
+/// Writes a given string with automatic indentation and a trailing newline
+/// into a writer.
 ///
-/// This macro works similarly to Rust's built-in `format!` macro,
-/// but prepends an indentation to the formatted string. The level of
-/// indentation is determined by the current call depth in the stack.
+/// This macro is the indented counterpart of Rust's `writeln!` macro. It
+/// accepts anything with a `write_fmt` method, like an [`std::io::Write`]
+/// implementation, and returns its result so errors can be propagated.
 ///
 /// # Example
 ///
 /// ```
-/// use iprint::iformat;
+/// use iprint::iwriteln;
+/// use std::io::Write;
 ///
-/// fn my_function() {
-///     let msg = iformat!("This will be indented based on call depth.");
-///     println!("{}", msg);
+/// fn main() -> std::io::Result<()> {
+///     let mut buf = Vec::new();
+///     iwriteln!(&mut buf, "top")?;
+///     nested(&mut buf)?;
+///     assert_eq!(String::from_utf8(buf).unwrap(), "top\n    nested\n");
+///     Ok(())
+/// }
+///
+/// fn nested(buf: &mut Vec<u8>) -> std::io::Result<()> {
+///     iwriteln!(buf, "nested")
 /// }
 /// ```
 #[macro_export]
-macro_rules! iformat {
-    ($($t:tt)*) => {{
-        let call_depth = $crate::call_depth!();
-        let indent = 4 * call_depth;
-        let text = format!($($t)*);
-        let indented_text: String = text
-            .lines()
-            .map(|line| format!("{:indent$}{}", "", line, indent=indent))
-            .collect::<Vec<_>>()
-            .join("\n");
-        indented_text
-    }}
+macro_rules! iwriteln {
+    ($dst:expr, $($t:tt)*) => {
+        writeln!($dst, "{}", $crate::iformat!($($t)*))
+    }
 }
 
-/// Prints a given string with automatic indentation to the console.
+/// Writes a given string with automatic indentation into a writer, without a
+/// trailing newline.
 ///
-/// This macro is an enhanced version of Rust's `println!` macro,
-/// adding automatic indentation based on the current call depth.
+/// This macro is the indented counterpart of Rust's `write!` macro.
 ///
 /// # Example
 ///
 /// ```
-/// use iprint::iprintln;
+/// use iprint::iwrite;
+/// use std::io::Write;
 ///
-/// fn another_function() {
-///     iprintln!("This is like println! but with automatic indentation.");
+/// fn main() -> std::io::Result<()> {
+///     let mut buf = Vec::new();
+///     iwrite!(&mut buf, "a")?;
+///     iwrite!(&mut buf, "b")?;
+///     assert_eq!(buf, b"ab");
+///     Ok(())
 /// }
 /// ```
 #[macro_export]
-macro_rules! iprintln {
-    ($($t:tt)*) => {
-        println!("{}", $crate::iformat!($($t)*))
+macro_rules! iwrite {
+    ($dst:expr, $($t:tt)*) => {
+        write!($dst, "{}", $crate::iformat!($($t)*))
     }
 }
 
+/// Indented versions of the `log` crate macros.
+///
+/// Like with the `log` macros, nothing is formatted unless the logger is
+/// enabled for the level and target of a message. Messages logged from deeper
+/// than [`log_max_depth`] are dropped before their arguments are evaluated as
+/// well, independently of the level filtering of `log`.
+///
+/// The macros refer to `log` through this crate with fully qualified paths,
+/// so they neither need the `log` macros in scope nor a dependency on `log`
+/// in the calling crate, and the feature can't leave `log` unresolvable. The
+/// `tests/no-log-dep` crate, which is built in CI, checks this without `log`
+/// in its dependencies.
+///
+/// ```
+/// // No `use log::...` is needed.
+/// use iprint::{iinfo, iwarn};
+///
+/// iinfo!("connected");
+/// iwarn!(target: "net", "retrying");
+/// ```
 #[cfg(feature = "log")]
 pub mod ilog {
+    #[doc(hidden)]
+    pub use log;
+
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! log_indented {
+        ($max_depth:expr, $lvl:expr, target: $target:expr, $($key:ident = $value:expr),+; $($t:tt)+) => {
+            match $lvl {
+                lvl => {
+                    if $crate::ilog::log::log_enabled!(target: $target, lvl)
+                        && $crate::call_depth!() <= $max_depth
+                    {
+                        $crate::ilog::log::log!(
+                            target: $target, lvl, $($key = $value),+; "{}", $crate::iformat!($($t)+)
+                        )
+                    }
+                }
+            }
+        };
+        ($max_depth:expr, $lvl:expr, target: $target:expr, $($t:tt)+) => {
+            match $lvl {
+                lvl => {
+                    if $crate::ilog::log::log_enabled!(target: $target, lvl)
+                        && $crate::call_depth!() <= $max_depth
+                    {
+                        $crate::ilog::log::log!(target: $target, lvl, "{}", $crate::iformat!($($t)+))
+                    }
+                }
+            }
+        };
+        ($max_depth:expr, $lvl:expr, $($key:ident = $value:expr),+; $($t:tt)+) => {
+            match $lvl {
+                lvl => {
+                    if $crate::ilog::log::log_enabled!(lvl) && $crate::call_depth!() <= $max_depth {
+                        $crate::ilog::log::log!(lvl, $($key = $value),+; "{}", $crate::iformat!($($t)+))
+                    }
+                }
+            }
+        };
+        ($max_depth:expr, $lvl:expr, $($t:tt)+) => {
+            match $lvl {
+                lvl => {
+                    if $crate::ilog::log::log_enabled!(lvl) && $crate::call_depth!() <= $max_depth {
+                        $crate::ilog::log::log!(lvl, "{}", $crate::iformat!($($t)+))
+                    }
+                }
+            }
+        };
+    }
+
     /// Logs a trace message with automatic indentation.
     ///
     /// This macro is an enhanced version of the `trace!` macro from the `log` crate,
@@ -241,12 +2363,47 @@ pub mod ilog {
     ///     }
     /// }
     /// ```
+    ///
+    /// The message is only formatted, and the call depth only determined, if the
+    /// logger is enabled for the level:
+    ///
+    /// ```
+    /// #[cfg(feature = "log")]
+    /// {
+    ///     use iprint::itrace;
+    ///     use log::{Level, Log, Metadata, Record};
+    ///     use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    ///     struct InfoOnly;
+    ///
+    ///     impl Log for InfoOnly {
+    ///         fn enabled(&self, metadata: &Metadata) -> bool {
+    ///             metadata.level() <= Level::Info
+    ///         }
+    ///
+    ///         fn log(&self, _: &Record) {}
+    ///
+    ///         fn flush(&self) {}
+    ///     }
+    ///
+    ///     log::set_logger(&InfoOnly).unwrap();
+    ///     log::set_max_level(log::LevelFilter::Trace);
+    ///
+    ///     static CALLS: AtomicUsize = AtomicUsize::new(0);
+    ///     let expensive = || {
+    ///         CALLS.fetch_add(1, Ordering::Relaxed);
+    ///         "state"
+    ///     };
+    ///     itrace!("{}", expensive());
+    ///     assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+    /// }
+    /// ```
     /// This macro is available only if the "log" feature is enabled.
     #[macro_export]
     macro_rules! itrace {
         ($($t:tt)*) => {
-            trace!("{}", $crate::iformat!($($t)*))
-        }
+            $crate::log_indented!($crate::log_max_depth(), $crate::ilog::log::Level::Trace, $($t)*)
+        };
     }
 
     /// Logs a debug message with automatic indentation.
@@ -271,8 +2428,8 @@ pub mod ilog {
     #[macro_export]
     macro_rules! idebug {
         ($($t:tt)*) => {
-            debug!("{}", $crate::iformat!($($t)*))
-        }
+            $crate::log_indented!($crate::log_max_depth(), $crate::ilog::log::Level::Debug, $($t)*)
+        };
     }
 
     /// Logs an informational message with automatic indentation.
@@ -293,12 +2450,98 @@ pub mod ilog {
     /// }
     /// ```
     ///
+    /// Like the `log` macros, all of the logging macros accept an optional
+    /// `target:` before the message, which is forwarded to the record:
+    ///
+    /// ```
+    /// #[cfg(feature = "log")]
+    /// {
+    ///     use iprint::iinfo;
+    ///     use log::{Log, Metadata, Record};
+    ///     use std::sync::Mutex;
+    ///
+    ///     static TARGETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    ///
+    ///     struct Recorder;
+    ///
+    ///     impl Log for Recorder {
+    ///         fn enabled(&self, _: &Metadata) -> bool {
+    ///             true
+    ///         }
+    ///
+    ///         fn log(&self, record: &Record) {
+    ///             TARGETS.lock().unwrap().push(record.target().to_string());
+    ///         }
+    ///
+    ///         fn flush(&self) {}
+    ///     }
+    ///
+    ///     log::set_logger(&Recorder).unwrap();
+    ///     log::set_max_level(log::LevelFilter::Info);
+    ///
+    ///     iinfo!(target: "net", "x");
+    ///     assert_eq!(*TARGETS.lock().unwrap(), ["net"]);
+    /// }
+    /// ```
+    ///
+    /// Key-value pairs before the message are forwarded as structured fields of
+    /// the record, using the syntax of the `log` macros. They go before the
+    /// message because pairs after it are named format arguments. Only the
+    /// message is indented:
+    ///
+    /// ```
+    /// #[cfg(feature = "log")]
+    /// {
+    ///     use iprint::iinfo;
+    ///     use log::kv::Key;
+    ///     use log::{Log, Metadata, Record};
+    ///     use std::sync::Mutex;
+    ///
+    ///     static RECORDS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+    ///
+    ///     struct Recorder;
+    ///
+    ///     impl Log for Recorder {
+    ///         fn enabled(&self, _: &Metadata) -> bool {
+    ///             true
+    ///         }
+    ///
+    ///         fn log(&self, record: &Record) {
+    ///             let user = record.key_values().get(Key::from("user")).unwrap();
+    ///             RECORDS
+    ///                 .lock()
+    ///                 .unwrap()
+    ///                 .push((record.args().to_string(), user.to_string()));
+    ///         }
+    ///
+    ///         fn flush(&self) {}
+    ///     }
+    ///
+    ///     fn main() {
+    ///         log::set_logger(&Recorder).unwrap();
+    ///         log::set_max_level(log::LevelFilter::Info);
+    ///         iprint::iformat!("top");
+    ///         login();
+    ///         assert_eq!(
+    ///             *RECORDS.lock().unwrap(),
+    ///             [("    logged in".to_string(), "alice".to_string())]
+    ///         );
+    ///     }
+    ///
+    ///     fn login() {
+    ///         iinfo!(user = "alice", attempt = 1; "logged in");
+    ///     }
+    ///
+    ///     main();
+    /// }
+    /// ```
+    ///
     /// This macro is available only if the "log" feature is enabled.
     #[macro_export]
     macro_rules! iinfo {
         ($($t:tt)*) => {
-            info!("{}", $crate::iformat!($($t)*))
-        }
+            $crate::log_indented!($crate::log_max_depth(), $crate::ilog::log::Level::Info, $($t)*)
+        };
     }
 
     /// Logs a warning message with automatic indentation.
@@ -323,8 +2566,8 @@ pub mod ilog {
     #[macro_export]
     macro_rules! iwarn {
         ($($t:tt)*) => {
-            warn!("{}", $crate::iformat!($($t)*))
-        }
+            $crate::log_indented!($crate::log_max_depth(), $crate::ilog::log::Level::Warn, $($t)*)
+        };
     }
 
     /// Logs an error message with automatic indentation.
@@ -349,7 +2592,150 @@ pub mod ilog {
     #[macro_export]
     macro_rules! ierror {
         ($($t:tt)*) => {
-            error!("{}", $crate::iformat!($($t)*))
-        }
+            $crate::log_indented!($crate::log_max_depth(), $crate::ilog::log::Level::Error, $($t)*)
+        };
+    }
+
+    /// Logs a message at a level chosen at runtime, with automatic indentation.
+    ///
+    /// This macro is an enhanced version of the `log!` macro from the `log`
+    /// crate, which is handy when the level is computed dynamically. The level
+    /// comes first, or second after an optional `target:`, and the rest of the
+    /// arguments are the same as for [`iinfo!`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// #[cfg(feature = "log")]
+    /// {
+    ///     use iprint::{ilog, iformat};
+    ///     use log::{Level, Log, Metadata, Record};
+    ///     use std::sync::Mutex;
+    ///
+    ///     static RECORDS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+    ///
+    ///     struct Recorder;
+    ///
+    ///     impl Log for Recorder {
+    ///         fn enabled(&self, _: &Metadata) -> bool {
+    ///             true
+    ///         }
+    ///
+    ///         fn log(&self, record: &Record) {
+    ///             let message = record.args().to_string();
+    ///             RECORDS.lock().unwrap().push((record.level(), message));
+    ///         }
+    ///
+    ///         fn flush(&self) {}
+    ///     }
+    ///
+    ///     fn report(failed: bool) {
+    ///         let level = if failed { Level::Error } else { Level::Info };
+    ///         ilog!(level, "finished");
+    ///     }
+    ///
+    ///     log::set_logger(&Recorder).unwrap();
+    ///     log::set_max_level(log::LevelFilter::Trace);
+    ///     iformat!("top");
+    ///     report(true);
+    ///     assert_eq!(
+    ///         *RECORDS.lock().unwrap(),
+    ///         [(Level::Error, "    finished".to_string())]
+    ///     );
+    /// }
+    /// ```
+    ///
+    /// This macro is available only if the "log" feature is enabled.
+    #[macro_export]
+    macro_rules! ilog {
+        (target: $target:expr, $lvl:expr, $($t:tt)+) => {
+            $crate::log_indented!($crate::log_max_depth(), $lvl, target: $target, $($t)+)
+        };
+        ($lvl:expr, $($t:tt)+) => {
+            $crate::log_indented!($crate::log_max_depth(), $lvl, $($t)+)
+        };
+    }
+
+    /// Logs an informational message with automatic indentation, at any call
+    /// depth.
+    ///
+    /// This is the same as [`iinfo!`], except that the message is logged even
+    /// from deeper than [`set_log_max_depth`](crate::set_log_max_depth)
+    /// allows. See [`ialways_warn!`](crate::ialways_warn).
+    ///
+    /// This macro is available only if the "log" feature is enabled.
+    #[macro_export]
+    macro_rules! ialways_info {
+        ($($t:tt)*) => {
+            $crate::log_indented!(usize::MAX, $crate::ilog::log::Level::Info, $($t)*)
+        };
+    }
+
+    /// Logs a warning message with automatic indentation, at any call depth.
+    ///
+    /// This is the same as [`iwarn!`], except that the message is logged even
+    /// from deeper than [`set_log_max_depth`](crate::set_log_max_depth)
+    /// allows, so important events aren't suppressed just because they happen
+    /// deep in the stack. The message is still indented by its call depth.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// #[cfg(feature = "log")]
+    /// {
+    ///     use iprint::{ialways_warn, iformat, iwarn, set_log_max_depth};
+    ///     use log::{Log, Metadata, Record};
+    ///     use std::sync::Mutex;
+    ///
+    ///     static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    ///
+    ///     struct Recorder;
+    ///
+    ///     impl Log for Recorder {
+    ///         fn enabled(&self, _: &Metadata) -> bool {
+    ///             true
+    ///         }
+    ///
+    ///         fn log(&self, record: &Record) {
+    ///             MESSAGES.lock().unwrap().push(record.args().to_string());
+    ///         }
+    ///
+    ///         fn flush(&self) {}
+    ///     }
+    ///
+    ///     fn deep() {
+    ///         iwarn!("suppressed");
+    ///         ialways_warn!("disk almost full");
+    ///     }
+    ///
+    ///     log::set_logger(&Recorder).unwrap();
+    ///     log::set_max_level(log::LevelFilter::Trace);
+    ///     set_log_max_depth(0);
+    ///     iformat!("top");
+    ///     deep();
+    ///     assert_eq!(*MESSAGES.lock().unwrap(), ["    disk almost full"]);
+    /// }
+    /// ```
+    ///
+    /// This macro is available only if the "log" feature is enabled.
+    #[macro_export]
+    macro_rules! ialways_warn {
+        ($($t:tt)*) => {
+            $crate::log_indented!(usize::MAX, $crate::ilog::log::Level::Warn, $($t)*)
+        };
+    }
+
+    /// Logs an error message with automatic indentation, at any call depth.
+    ///
+    /// This is the same as [`ierror!`], except that the message is logged even
+    /// from deeper than [`set_log_max_depth`](crate::set_log_max_depth)
+    /// allows. See [`ialways_warn!`](crate::ialways_warn).
+    ///
+    /// This macro is available only if the "log" feature is enabled.
+    #[macro_export]
+    macro_rules! ialways_error {
+        ($($t:tt)*) => {
+            $crate::log_indented!(usize::MAX, $crate::ilog::log::Level::Error, $($t)*)
+        };
     }
 }
@@ -0,0 +1,118 @@
+//! Export of the trace scopes as OpenTelemetry spans.
+//!
+//! With the "otel" feature, every [`itrace_scope!`](crate::itrace_scope)
+//! also starts a span with the name of the scope, which ends once the guard is
+//! dropped. A scope started while another one is alive on the same thread
+//! starts a child span of the innermost one, so the span hierarchy an exporter
+//! receives matches the indentation of the printed trace. Outside of any scope,
+//! the span is a child of the current OpenTelemetry context. The spans are
+//! created with the [global tracer provider](opentelemetry::global), under the
+//! tracer name `iprint`, and carry the source location of the scope.
+//!
+//! The span isn't attached as the current context, so each [`TraceGuard`]
+//! ends its own span even if the guards are dropped out of order.
+//!
+//! # Example
+//!
+//! ```
+//! use iprint::itrace_scope;
+//! use opentelemetry::global;
+//! use opentelemetry::trace::SpanId;
+//! use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+//!
+//! let exporter = InMemorySpanExporter::default();
+//! let provider = SdkTracerProvider::builder()
+//!     .with_simple_exporter(exporter.clone())
+//!     .build();
+//! global::set_tracer_provider(provider);
+//!
+//! {
+//!     let _request = itrace_scope!("request");
+//!     let _parse = itrace_scope!("parse");
+//! }
+//!
+//! let spans = exporter.get_finished_spans().unwrap();
+//! let names: Vec<_> = spans.iter().map(|span| &span.name).collect();
+//! assert_eq!(names, ["parse", "request"]);
+//! assert_eq!(spans[0].parent_span_id, spans[1].span_context.span_id());
+//! assert_eq!(spans[1].parent_span_id, SpanId::INVALID);
+//!
+//! // Dropping the outer guard first ends the outer span, and the next scope
+//! // is still a child of the inner one.
+//! exporter.reset();
+//! let outer = itrace_scope!("outer");
+//! let inner = itrace_scope!("inner");
+//! drop(outer);
+//! let leaf = itrace_scope!("leaf");
+//! drop(leaf);
+//! drop(inner);
+//!
+//! let spans = exporter.get_finished_spans().unwrap();
+//! let names: Vec<_> = spans.iter().map(|span| &span.name).collect();
+//! assert_eq!(names, ["outer", "leaf", "inner"]);
+//! let (outer, leaf, inner) = (&spans[0], &spans[1], &spans[2]);
+//! assert_eq!(inner.parent_span_id, outer.span_context.span_id());
+//! assert_eq!(leaf.parent_span_id, inner.span_context.span_id());
+//! ```
+//!
+//! This module is available only if the "otel" feature is enabled.
+//!
+//! [`TraceGuard`]: crate::TraceGuard
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use opentelemetry::trace::{SpanId, TraceContextExt, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+
+std::thread_local! {
+    /// The spans of the scopes that are alive on this thread, in the order
+    /// they were started.
+    static SCOPES: RefCell<Vec<Context>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The span of a trace scope, which ends once it is dropped.
+pub(crate) struct SpanScope {
+    cx: Context,
+}
+
+impl SpanScope {
+    pub(crate) fn start(name: &str, location: (&'static str, u32)) -> SpanScope {
+        let (file, line) = location;
+        let tracer = global::tracer("iprint");
+        let parent = SCOPES
+            .with(|scopes| scopes.borrow().last().cloned())
+            .unwrap_or_else(Context::current);
+        let span = tracer
+            .span_builder(String::from(name))
+            .with_attributes([
+                KeyValue::new("code.file.path", file),
+                KeyValue::new("code.line.number", i64::from(line)),
+            ])
+            .start_with_context(&tracer, &parent);
+        let cx = parent.with_span(span);
+        SCOPES.with(|scopes| scopes.borrow_mut().push(cx.clone()));
+        SpanScope { cx }
+    }
+
+    fn span_id(&self) -> SpanId {
+        self.cx.span().span_context().span_id()
+    }
+}
+
+impl Drop for SpanScope {
+    fn drop(&mut self) {
+        let id = self.span_id();
+        // The guards may be dropped out of order.
+        let _ = SCOPES.try_with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+            if let Some(i) = scopes
+                .iter()
+                .rposition(|cx| cx.span().span_context().span_id() == id)
+            {
+                scopes.remove(i);
+            }
+        });
+        self.cx.span().end();
+    }
+}
@@ -0,0 +1,905 @@
+#[cfg(feature = "std")]
+use crate::sync::Setting;
+#[cfg(feature = "std")]
+use alloc::string::String;
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "time")]
+static TIMESTAMP_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "std")]
+static ELAPSED_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "std")]
+static THREAD_NAME_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "std")]
+static LOCATION_ENABLED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "std")]
+static LOCATION_STRIP_PREFIX: Setting<Option<&'static str>> = Setting::new(None);
+#[cfg(feature = "std")]
+static MODULE_FILTER: std::sync::Mutex<Option<alloc::vec::Vec<String>>> =
+    std::sync::Mutex::new(None);
+#[cfg(feature = "std")]
+static MODULE_FILTER_SET: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "std")]
+static CLOCK: std::sync::Mutex<Option<Instant>> = std::sync::Mutex::new(None);
+#[cfg(all(feature = "log", feature = "std"))]
+static PRINTLN_VIA_LOG: Setting<Option<log::Level>> = Setting::new(None);
+
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Enables or disables prefixing every printed line with the time of day.
+///
+/// The timestamp, like `[12:34:56.789]`, is written in local time if the
+/// offset can be determined and in UTC otherwise. It goes before the
+/// indentation, so the columns of each depth stay aligned. Only the printing
+/// macros are affected. Timestamps are disabled by default.
+///
+/// # Example
+///
+/// ```
+/// #[cfg(feature = "time")]
+/// {
+///     iprint::set_timestamp_enabled(true);
+///     iprint::iprintln!("printed after a timestamp");
+/// }
+/// ```
+///
+/// This function is available only if the "time" feature is enabled.
+#[cfg(feature = "time")]
+pub fn set_timestamp_enabled(enabled: bool) {
+    TIMESTAMP_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether printed lines are prefixed with the time of day.
+///
+/// This function is available only if the "time" feature is enabled.
+#[cfg(feature = "time")]
+pub fn timestamp_enabled() -> bool {
+    TIMESTAMP_ENABLED.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "time")]
+fn timestamp() -> String {
+    let now = time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+    alloc::format!(
+        "[{:02}:{:02}:{:02}.{:03}] ",
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.millisecond()
+    )
+}
+
+/// Enables or disables prefixing every printed line with the elapsed time.
+///
+/// The prefix, like `[  123ms]`, counts the milliseconds since the clock was
+/// started, which happens when the prefix is first enabled or
+/// [`reset_clock`] is called. It goes before the indentation and after a
+/// timestamp, if those are enabled too. Only the printing macros are affected.
+/// The prefix is disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_elapsed_prefix_enabled, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+/// set_elapsed_prefix_enabled(true);
+/// iprintln!("started");
+///
+/// // Prints something like `[    0ms] started`.
+/// let captured = CAPTURED.lock().unwrap();
+/// assert!(captured.starts_with('['));
+/// assert!(captured.ends_with("ms] started\n"));
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_elapsed_prefix_enabled(enabled: bool) {
+    if enabled {
+        lock_clock().get_or_insert_with(Instant::now);
+    }
+    ELAPSED_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether printed lines are prefixed with the elapsed time.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn elapsed_prefix_enabled() -> bool {
+    ELAPSED_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Restarts the clock of the elapsed time prefix from zero.
+///
+/// See [`set_elapsed_prefix_enabled`].
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn reset_clock() {
+    *lock_clock() = Some(Instant::now());
+}
+
+#[cfg(feature = "std")]
+fn lock_clock() -> std::sync::MutexGuard<'static, Option<Instant>> {
+    CLOCK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(feature = "std")]
+fn elapsed() -> String {
+    let start = *lock_clock().get_or_insert_with(Instant::now);
+    alloc::format!("[{:>5}ms] ", start.elapsed().as_millis())
+}
+
+/// Enables or disables prefixing every printed line with the name of the
+/// thread that printed it.
+///
+/// The prefix, like `[worker-1]`, tells apart the interleaved output of
+/// several threads, whose indentation is tracked separately. Threads without
+/// a name are shown as `[<unnamed>]`. It goes before the indentation and
+/// after a timestamp and the elapsed time, if those are enabled too. Only the
+/// printing macros are affected. The prefix is disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_output_sink, set_thread_name_prefix};
+/// use std::sync::Mutex;
+/// use std::thread;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+/// set_thread_name_prefix(true);
+/// for name in ["reader", "writer"] {
+///     thread::Builder::new()
+///         .name(name.into())
+///         .spawn(|| iprintln!("working\ndone"))
+///         .unwrap()
+///         .join()
+///         .unwrap();
+/// }
+/// assert_eq!(
+///     *CAPTURED.lock().unwrap(),
+///     "[reader] working\n[reader] done\n[writer] working\n[writer] done\n"
+/// );
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_thread_name_prefix(enabled: bool) {
+    THREAD_NAME_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether printed lines are prefixed with the name of their thread.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn thread_name_prefix() -> bool {
+    THREAD_NAME_ENABLED.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+fn thread_name() -> String {
+    let thread = std::thread::current();
+    alloc::format!("[{}] ", thread.name().unwrap_or("<unnamed>"))
+}
+
+/// Prepends `prefix` to every line of `text`.
+#[cfg(feature = "std")]
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    let mut prefixed = String::with_capacity(text.len() + prefix.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            prefixed.push('\n');
+        }
+        prefixed.push_str(prefix);
+        prefixed.push_str(line);
+    }
+    prefixed
+}
+
+#[cfg(feature = "std")]
+type Sink = alloc::boxed::Box<dyn FnMut(&str) + Send>;
+
+#[cfg(feature = "std")]
+static OUTPUT_SINK: std::sync::Mutex<Option<Sink>> = std::sync::Mutex::new(None);
+
+/// Redirects everything the printing macros write to stdout into `sink`.
+///
+/// The sink receives exactly the text that would have been printed, that is
+/// indented, prefixed with any timestamps and including the trailing newline
+/// of [`iprintln!`](crate::iprintln), but never colored. This makes it
+/// possible to show the output in a GUI or capture it in a test without
+/// changing the call sites. Output to stderr is not affected.
+///
+/// The sink is shared by all threads and called while a lock is held, so it
+/// must not print through this crate itself.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     iprintln!("top");
+///     nested();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "top\n    nested\n");
+/// }
+///
+/// fn nested() {
+///     iprintln!("nested");
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_output_sink(sink: impl FnMut(&str) + Send + 'static) {
+    *lock_sink() = Some(alloc::boxed::Box::new(sink));
+}
+
+/// Removes the sink set with [`set_output_sink`], so the printing macros
+/// write to stdout again.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn clear_output_sink() {
+    *lock_sink() = None;
+}
+
+/// Restricts the printing macros to the modules that match `filter`.
+///
+/// The filter is a comma-separated list of module paths, like
+/// `myapp::net,myapp::db`, and a module matches if it is one of them or
+/// nested in one of them. Macros invoked in any other module, as given by
+/// `module_path!()` at the call site, print nothing, don't evaluate their
+/// format arguments and report 0 printed lines. They still record the depth
+/// of their frame, so the output of the matching modules is indented as
+/// usual. Formatting macros like
+/// [`iformat!`](crate::iformat) return their text to the caller, and are not
+/// affected. The filter applies to every thread.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_module_filter, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// mod net {
+///     pub fn connect() {
+///         iprint::iprintln!("connecting");
+///     }
+/// }
+///
+/// mod network {
+///     pub fn connect() {
+///         iprint::iprintln!("also connecting");
+///     }
+/// }
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     set_module_filter(&format!("{}::net", module_path!()));
+///     assert_eq!(iprintln!("starting"), 0);
+///     net::connect();
+///     network::connect();
+///     assert_eq!(*CAPTURED.lock().unwrap(), "    connecting\n");
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_module_filter(filter: &str) {
+    let modules = filter
+        .split(',')
+        .map(str::trim)
+        .filter(|module| !module.is_empty())
+        .map(String::from)
+        .collect();
+    *lock_module_filter() = Some(modules);
+    MODULE_FILTER_SET.store(true, Ordering::Relaxed);
+}
+
+/// Removes the filter set with [`set_module_filter`], so the printing macros
+/// print in every module again.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn clear_module_filter() {
+    MODULE_FILTER_SET.store(false, Ordering::Relaxed);
+    *lock_module_filter() = None;
+}
+
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub fn module_enabled(module: &str) -> bool {
+    if !MODULE_FILTER_SET.load(Ordering::Relaxed) {
+        return true;
+    }
+    match lock_module_filter().as_ref() {
+        Some(modules) => modules.iter().any(|filter| {
+            module
+                .strip_prefix(filter.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        }),
+        None => true,
+    }
+}
+
+#[cfg(feature = "std")]
+fn lock_module_filter() -> std::sync::MutexGuard<'static, Option<alloc::vec::Vec<String>>> {
+    MODULE_FILTER
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(feature = "std")]
+fn lock_sink() -> std::sync::MutexGuard<'static, Option<Sink>> {
+    OUTPUT_SINK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// A structured event received by the sink set with [`set_event_sink`].
+///
+/// This type is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A level of explicit depth was entered, e.g. by [`scope`](crate::scope),
+    /// and the lines printed within it are at this depth.
+    Enter(usize),
+    /// The level at this depth was left again.
+    Exit(usize),
+    /// A message was printed at `depth`.
+    ///
+    /// `text` is the message as it was formatted, without any indentation,
+    /// timestamps or locations.
+    Line { depth: usize, text: String },
+}
+
+#[cfg(feature = "std")]
+type EventSink = alloc::boxed::Box<dyn FnMut(TraceEvent) + Send>;
+
+#[cfg(feature = "std")]
+static EVENT_SINK: std::sync::Mutex<Option<EventSink>> = std::sync::Mutex::new(None);
+
+/// Sends a [`TraceEvent`] to `sink` whenever a scope is entered or left and
+/// whenever a message is printed.
+///
+/// Scopes are entered and left by [`scope`](crate::scope) guards and by
+/// [`indent()`](crate::indent()) and [`dedent`](crate::dedent). Lines are
+/// reported by all printing macros, to stdout as well as to stderr. Unlike
+/// [`set_output_sink`], this doesn't change what is printed, and gives a
+/// trace viewer the structure of the output instead of its text, e.g. to
+/// render it as a collapsible tree.
+///
+/// The sink is shared by all threads and called while a lock is held, so it
+/// must not print through this crate itself.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, scope, set_event_sink, TraceEvent};
+/// use std::sync::Mutex;
+///
+/// static EVENTS: Mutex<Vec<TraceEvent>> = Mutex::new(Vec::new());
+///
+/// fn main() {
+///     set_event_sink(|event| EVENTS.lock().unwrap().push(event));
+///     outer();
+///     assert_eq!(
+///         *EVENTS.lock().unwrap(),
+///         [
+///             TraceEvent::Enter(1),
+///             TraceEvent::Line { depth: 1, text: "outer".into() },
+///             TraceEvent::Enter(2),
+///             TraceEvent::Line { depth: 2, text: "inner".into() },
+///             TraceEvent::Exit(2),
+///             TraceEvent::Exit(1),
+///         ]
+///     );
+/// }
+///
+/// fn outer() {
+///     let _guard = scope();
+///     iprintln!("outer");
+///     inner();
+/// }
+///
+/// fn inner() {
+///     let _guard = scope();
+///     iprintln!("inner");
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_event_sink(sink: impl FnMut(TraceEvent) + Send + 'static) {
+    *lock_event_sink() = Some(alloc::boxed::Box::new(sink));
+}
+
+/// Removes the sink set with [`set_event_sink`].
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn clear_event_sink() {
+    *lock_event_sink() = None;
+}
+
+/// Sends the event built by `event` to the event sink and the
+/// [recording](crate::start_recording), if either is active.
+#[cfg(feature = "std")]
+pub(crate) fn send_event(event: impl FnOnce() -> TraceEvent) {
+    let recording = crate::record::is_recording();
+    let mut sink = lock_event_sink();
+    if !recording && sink.is_none() {
+        return;
+    }
+    let event = event();
+    match sink.as_mut() {
+        Some(sink) if recording => {
+            crate::record::record(event.clone());
+            sink(event);
+        }
+        Some(sink) => sink(event),
+        None => crate::record::record(event),
+    }
+}
+
+#[cfg(feature = "std")]
+fn lock_event_sink() -> std::sync::MutexGuard<'static, Option<EventSink>> {
+    EVENT_SINK
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// The format in which the printing macros write their messages.
+///
+/// This type is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Messages are indented by their depth. This is the default.
+    #[default]
+    Text,
+    /// Every message is written as a JSON object on a line of its own, like
+    /// `{"depth":2,"message":"..."}`, with the depth as a field instead of
+    /// indentation.
+    Json,
+}
+
+#[cfg(feature = "std")]
+static OUTPUT_FORMAT: Setting<OutputFormat> = Setting::new(OutputFormat::Text);
+
+/// Sets the format in which the printing macros write their messages.
+///
+/// With [`OutputFormat::Json`], every call of a printing macro writes one
+/// JSON object and a newline, even [`iprint!`](crate::iprint). A multi-line
+/// message stays a single JSON string with embedded newlines. Timestamps,
+/// elapsed times and colors are left out, so every line can be parsed as is.
+/// [`iformat!`](crate::iformat) and the logging macros are not affected.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_format, set_output_sink, OutputFormat};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     set_format(OutputFormat::Json);
+///     iprintln!("top");
+///     nested();
+///     assert_eq!(
+///         *CAPTURED.lock().unwrap(),
+///         "{\"depth\":0,\"message\":\"top\"}\n\
+///          {\"depth\":1,\"message\":\"a \\\"quoted\\\"\\nmessage\"}\n"
+///     );
+/// }
+///
+/// fn nested() {
+///     iprintln!("a \"quoted\"\nmessage");
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_format(format: OutputFormat) {
+    OUTPUT_FORMAT.set(format);
+}
+
+/// Returns the format in which the printing macros write their messages.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get()
+}
+
+/// Sends everything the printing macros write to stdout to the `log` crate
+/// at `level`, or prints it again if `level` is `None`.
+///
+/// The indented message is logged as a record with the target `iprint` and
+/// the file and line of the macro call, so the installed logger filters and
+/// formats it like any other record. This applies instead of the
+/// [output sink](set_output_sink), the [format](set_format) and the
+/// timestamps and locations iprint adds itself. Output to stderr is not
+/// affected. By default the messages are printed.
+///
+/// # Example
+///
+/// ```
+/// #[cfg(feature = "log")]
+/// {
+///     use iprint::{iprintln, set_println_via_log};
+///     use log::{Level, Log, Metadata, Record};
+///     use std::sync::Mutex;
+///
+///     static RECORDS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+///
+///     struct Recorder;
+///
+///     impl Log for Recorder {
+///         fn enabled(&self, _: &Metadata) -> bool {
+///             true
+///         }
+///
+///         fn log(&self, record: &Record) {
+///             let message = record.args().to_string();
+///             RECORDS.lock().unwrap().push((record.level(), message));
+///         }
+///
+///         fn flush(&self) {}
+///     }
+///
+///     fn nested() {
+///         iprintln!("nested");
+///     }
+///
+///     log::set_logger(&Recorder).unwrap();
+///     log::set_max_level(log::LevelFilter::Trace);
+///     set_println_via_log(Some(Level::Debug));
+///     iprintln!("top");
+///     nested();
+///     assert_eq!(
+///         *RECORDS.lock().unwrap(),
+///         [
+///             (Level::Debug, "top".to_string()),
+///             (Level::Debug, "    nested".to_string()),
+///         ]
+///     );
+/// }
+/// ```
+///
+/// This function is available only if the "log" and "std" features are
+/// enabled.
+#[cfg(all(feature = "log", feature = "std"))]
+pub fn set_println_via_log(level: Option<log::Level>) {
+    PRINTLN_VIA_LOG.set(level);
+}
+
+/// Returns the level at which output to stdout is sent to the `log` crate,
+/// if it is.
+///
+/// This function is available only if the "log" and "std" features are
+/// enabled.
+#[cfg(all(feature = "log", feature = "std"))]
+pub fn println_via_log() -> Option<log::Level> {
+    PRINTLN_VIA_LOG.get()
+}
+
+/// Logs `text` as a record of the macro call at `file` and `line`.
+#[cfg(all(feature = "log", feature = "std"))]
+fn log_line(level: log::Level, text: &str, file: &'static str, line: u32) {
+    let metadata = log::Metadata::builder()
+        .level(level)
+        .target("iprint")
+        .build();
+    if level > log::max_level() || !log::logger().enabled(&metadata) {
+        return;
+    }
+    log::logger().log(
+        &log::Record::builder()
+            .metadata(metadata)
+            .args(format_args!("{}", text))
+            .file_static(Some(file))
+            .line(Some(line))
+            .build(),
+    );
+}
+
+/// Enables or disables prefixing printed messages with their source location.
+///
+/// The location, like `src/main.rs:42: `, is that of the printing macro that
+/// wrote the message, captured with `file!()` and `line!()` where it is
+/// invoked. It goes before the indentation of the first line of a message,
+/// and the continuation lines are padded by its width, so the message stays
+/// aligned. In the JSON [format](set_format), the location is added as the
+/// `file` and `line` fields instead. Locations are disabled by default.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_location_enabled, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     set_location_enabled(true);
+///     let line = line!() + 1;
+///     iprintln!("top");
+///     assert_eq!(
+///         *CAPTURED.lock().unwrap(),
+///         format!("{}:{}: top\n", file!(), line)
+///     );
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_location_enabled(enabled: bool) {
+    LOCATION_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether printed messages are prefixed with their source location.
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn location_enabled() -> bool {
+    LOCATION_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sets a prefix that is removed from the file paths of source locations.
+///
+/// Paths that don't start with the prefix are shown in full. This keeps the
+/// [location](set_location_enabled) short, e.g. with the prefix `src/`, a
+/// message from `src/net/mod.rs` is prefixed with `net/mod.rs:42: `.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, set_location_enabled, set_location_strip_prefix, set_output_sink};
+/// use std::sync::Mutex;
+///
+/// static CAPTURED: Mutex<String> = Mutex::new(String::new());
+///
+/// fn main() {
+///     set_output_sink(|text| CAPTURED.lock().unwrap().push_str(text));
+///     set_location_enabled(true);
+///     set_location_strip_prefix("src/");
+///     let line = line!() + 1;
+///     iprintln!("top");
+///     assert_eq!(
+///         *CAPTURED.lock().unwrap(),
+///         format!("{}:{}: top\n", file!().strip_prefix("src/").unwrap(), line)
+///     );
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub fn set_location_strip_prefix(prefix: &'static str) {
+    LOCATION_STRIP_PREFIX.set(Some(prefix));
+}
+
+/// Prints `text` at `depth`, the same way `iprintln!` does.
+#[cfg(feature = "std")]
+pub(crate) fn println_at(depth: usize, text: &str, stream: Stream, location: (&'static str, u32)) {
+    emit(depth, text, stream, true, location);
+}
+
+/// Writes `message` at `depth` to `stream` in the configured format and
+/// returns the number of lines written.
+///
+/// `location` is the file and line of the macro invocation.
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub fn emit(
+    depth: usize,
+    message: &str,
+    stream: Stream,
+    newline: bool,
+    location: (&'static str, u32),
+) -> usize {
+    emit_with(depth, message, stream, newline, location, None)
+}
+
+/// Prints `message` like [`emit`], with `unit` replacing the configured width
+/// and character of a level if given.
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub fn emit_with(
+    depth: usize,
+    message: &str,
+    stream: Stream,
+    newline: bool,
+    location: (&'static str, u32),
+    unit: Option<(usize, char)>,
+) -> usize {
+    send_event(|| TraceEvent::Line {
+        depth,
+        text: String::from(message),
+    });
+    if let Stream::Stdout = stream {
+        if crate::record::is_recording() {
+            return line_count(&crate::indent_text_with(depth, message, unit));
+        }
+    }
+    let (file, line) = location;
+    #[cfg(feature = "log")]
+    if let (Stream::Stdout, Some(level)) = (stream, println_via_log()) {
+        let text = crate::indent_text_with(depth, message, unit);
+        log_line(level, &text, file, line);
+        return line_count(&text);
+    }
+    let file = match LOCATION_STRIP_PREFIX.get() {
+        Some(prefix) => file.strip_prefix(prefix).unwrap_or(file),
+        None => file,
+    };
+    match output_format() {
+        OutputFormat::Text => {
+            let mut text = crate::indent_text_with(depth, message, unit);
+            let lines = line_count(&text);
+            if location_enabled() {
+                text = locate(&text, file, line);
+            }
+            write_out(stamp(text), depth, stream, newline);
+            lines
+        }
+        OutputFormat::Json => {
+            let mut text = alloc::format!("{{\"depth\":{},", depth);
+            if location_enabled() {
+                text.push_str("\"file\":");
+                push_json_string(&mut text, file);
+                text.push_str(&alloc::format!(",\"line\":{},", line));
+            }
+            text.push_str("\"message\":");
+            push_json_string(&mut text, message);
+            text.push('}');
+            write_out(text, depth, stream, true);
+            1
+        }
+    }
+}
+
+/// Returns the number of lines that writing the indented `text` takes up.
+///
+/// This is counted after indenting, which drops a trailing line break unless
+/// the [line endings are preserved](crate::set_preserve_line_endings), and
+/// may add line breaks by wrapping.
+#[cfg(feature = "std")]
+fn line_count(text: &str) -> usize {
+    text.matches('\n').count() + 1
+}
+
+/// Prefixes the first line of `text` with the location, and pads the others
+/// by its width.
+#[cfg(feature = "std")]
+fn locate(text: &str, file: &str, line: u32) -> String {
+    let location = alloc::format!("{}:{}: ", file, line);
+    let padding = " ".repeat(location.chars().count());
+    let mut located = String::with_capacity(text.len() + location.len());
+    for (i, text_line) in text.split('\n').enumerate() {
+        if i == 0 {
+            located.push_str(&location);
+        } else {
+            located.push('\n');
+            located.push_str(&padding);
+        }
+        located.push_str(text_line);
+    }
+    located
+}
+
+/// Writes finished `text` to the sink or `stream`.
+#[cfg(feature = "std")]
+fn write_out(mut text: String, depth: usize, stream: Stream, newline: bool) {
+    if let Stream::Stdout = stream {
+        #[cfg(feature = "testing")]
+        if crate::testing::capture_text(&text, newline) {
+            return;
+        }
+        if let Some(sink) = lock_sink().as_mut() {
+            if newline {
+                text.push('\n');
+            }
+            sink(&text);
+            return;
+        }
+    }
+    #[cfg(feature = "color")]
+    let text = if output_format() == OutputFormat::Text {
+        crate::color::paint(text, depth, stream)
+    } else {
+        text
+    };
+    #[cfg(not(feature = "color"))]
+    let _ = depth;
+    // The lock is reentrant, so the print macros below take it again without
+    // blocking. Holding it keeps the lines of one message together even if
+    // the macros write them separately, while output capturing in tests
+    // still sees them.
+    match stream {
+        Stream::Stdout => {
+            let _stdout = std::io::stdout().lock();
+            if newline {
+                std::println!("{}", text);
+            } else {
+                std::print!("{}", text);
+            }
+        }
+        Stream::Stderr => {
+            let _stderr = std::io::stderr().lock();
+            if newline {
+                std::eprintln!("{}", text);
+            } else {
+                std::eprint!("{}", text);
+            }
+        }
+    }
+}
+
+/// Appends `value` to `buf` as a quoted and escaped JSON string.
+#[cfg(feature = "std")]
+fn push_json_string(buf: &mut String, value: &str) {
+    use core::fmt::Write;
+
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+/// Prefixes every line of `text` with the enabled timestamps.
+#[cfg(feature = "std")]
+fn stamp(text: String) -> String {
+    let mut prefix = String::new();
+    #[cfg(feature = "time")]
+    if timestamp_enabled() {
+        prefix.push_str(&timestamp());
+    }
+    if elapsed_prefix_enabled() {
+        prefix.push_str(&elapsed());
+    }
+    if thread_name_prefix() {
+        prefix.push_str(&thread_name());
+    }
+    if prefix.is_empty() {
+        return text;
+    }
+    prefix_lines(&text, &prefix)
+}
@@ -0,0 +1,93 @@
+use crate::{explicit_depth, indent_text, offset_depth, STACK};
+use alloc::string::String;
+use std::panic::{self, PanicHookInfo};
+
+/// Installs a panic hook that prints the panic message indented by the
+/// current call depth, before handing the panic on to the previous hook.
+///
+/// This lines up a panic deep in traced code with the trace around it. The
+/// indented message, like `panicked at src/main.rs:5:5: bad state`, is
+/// written to stderr, and the previous hook runs afterwards, so the default
+/// report and any custom hook installed earlier are kept. Installing the
+/// hook twice prints the message twice.
+///
+/// The hook runs inside the panic machinery, deeper than the function that
+/// panicked, so the depth is that of the innermost frame that recorded its
+/// depth with one of the macros, or the depth of the alive guards if there
+/// are any.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, install_panic_hook, iprintln};
+/// use std::process::Command;
+///
+/// fn nested() {
+///     iprintln!("parsing");
+///     panic!("bad state");
+/// }
+///
+/// fn main() {
+///     if std::env::var_os("IPRINT_CHILD").is_some() {
+///         install_panic_hook();
+///         iformat!("top");
+///         nested();
+///     }
+///
+///     // Runs this example again, to read what it writes to stderr.
+///     let output = Command::new(std::env::current_exe().unwrap())
+///         .env("IPRINT_CHILD", "1")
+///         .output()
+///         .unwrap();
+///     let stderr = String::from_utf8(output.stderr).unwrap();
+///     assert!(stderr.starts_with("    panicked at "));
+///     assert!(stderr.lines().next().unwrap().ends_with(": bad state"));
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(alloc::boxed::Box::new(move |info| {
+        std::eprintln!("{}", indent_text(panic_depth(), &panic_message(info)));
+        previous(info);
+    }));
+}
+
+/// Returns the depth of the innermost frame that is known to be alive.
+fn panic_depth() -> usize {
+    if !cfg!(feature = "enabled") {
+        return 0;
+    }
+    let depth = explicit_depth();
+    let depth = if depth > 0
+        || !cfg!(any(
+            feature = "portable",
+            target_arch = "x86_64",
+            target_arch = "aarch64"
+        )) {
+        depth
+    } else {
+        // The frames can't be popped by the stack pointer of the hook, which
+        // is deeper than all of them.
+        STACK
+            .try_with(|stack| stack.try_borrow().map_or(0, |s| s.len().saturating_sub(1)))
+            .unwrap_or(0)
+    };
+    offset_depth(depth)
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.as_str(),
+            None => "Box<dyn Any>",
+        },
+    };
+    match info.location() {
+        Some(location) => alloc::format!("panicked at {}: {}", location, message),
+        None => alloc::format!("panicked: {}", message),
+    }
+}
@@ -0,0 +1,248 @@
+use crate::TraceEvent;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+
+/// The events recorded so far, and the threads they came from.
+struct Recorded {
+    events: Vec<(ThreadId, TraceEvent)>,
+    /// The threads in the order of their first event, with their names.
+    threads: Vec<(ThreadId, String)>,
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static RECORDED: Mutex<Recorded> = Mutex::new(Recorded {
+    events: Vec::new(),
+    threads: Vec::new(),
+});
+
+fn lock_recorded() -> MutexGuard<'static, Recorded> {
+    RECORDED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Starts recording the [`TraceEvent`]s of all threads instead of printing to
+/// stdout, discarding anything recorded before.
+///
+/// While recording, the printing macros write nothing to stdout, and their
+/// messages are kept together with the entered and left scopes until
+/// [`stop_recording`] is called. Output to stderr is still printed. An
+/// [event sink](crate::set_event_sink) receives the events as well. The
+/// recorded run can be rendered as a tree with [`render_tree`] afterwards.
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn start_recording() {
+    let mut recorded = lock_recorded();
+    recorded.events.clear();
+    recorded.threads.clear();
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Stops the recording started with [`start_recording`], and returns the
+/// events recorded on all threads, in the order they happened.
+///
+/// The events stay recorded for [`render_tree`] until the next recording
+/// starts.
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn stop_recording() -> Vec<TraceEvent> {
+    let recorded = lock_recorded();
+    RECORDING.store(false, Ordering::Relaxed);
+    recorded
+        .events
+        .iter()
+        .map(|(_, event)| event.clone())
+        .collect()
+}
+
+/// Returns whether the events are being recorded.
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+pub(crate) fn record(event: TraceEvent) {
+    let mut recorded = lock_recorded();
+    if is_recording() {
+        let thread = thread::current();
+        let id = thread.id();
+        if !recorded.threads.iter().any(|&(known, _)| known == id) {
+            let name = match thread.name() {
+                Some(name) => String::from(name),
+                None => alloc::format!("{:?}", id),
+            };
+            recorded.threads.push((id, name));
+        }
+        recorded.events.push((id, event));
+    }
+}
+
+/// A recorded message and the messages nested in it.
+struct Node {
+    text: String,
+    children: Vec<usize>,
+}
+
+/// Renders the messages of the last recording as a tree drawn with
+/// box-drawing characters.
+///
+/// Every message is a node, and the messages that were printed from deeper
+/// than it, up to the next one at its depth or shallower, are its children.
+/// The messages at the shallowest depths become the roots. The lines of a
+/// multi-line message stay together in their node.
+///
+/// Since every thread has a depth of its own, the messages of each thread form
+/// a tree of their own, and the trees follow each other in the order the
+/// threads recorded their first event. If more than one thread recorded
+/// messages, each tree is preceded by a line with the name of its thread, or
+/// its id if the thread has no name.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iprintln, render_tree, start_recording, stop_recording};
+///
+/// fn main() {
+///     start_recording();
+///     iprintln!("start");
+///     parse();
+///     iprintln!("end");
+///     stop_recording();
+///     assert_eq!(
+///         render_tree(),
+///         "start\n├── parse\n│   ├── token a\n│   └── token b\n└── parsed\nend\n"
+///     );
+/// }
+///
+/// fn parse() {
+///     iprintln!("parse");
+///     token("a");
+///     token("b");
+///     iprintln!("parsed");
+/// }
+///
+/// fn token(name: &str) {
+///     iprintln!("token {}", name);
+/// }
+/// ```
+///
+/// With several threads:
+///
+/// ```
+/// use iprint::{iprintln, render_tree, start_recording, stop_recording};
+/// use std::thread;
+///
+/// fn main() {
+///     start_recording();
+///     iprintln!("spawning");
+///     thread::Builder::new()
+///         .name(String::from("worker"))
+///         .spawn(work)
+///         .unwrap()
+///         .join()
+///         .unwrap();
+///     iprintln!("joined");
+///     stop_recording();
+///     assert_eq!(
+///         render_tree(),
+///         "main:\nspawning\njoined\nworker:\nwork\n└── step\n"
+///     );
+/// }
+///
+/// fn work() {
+///     iprintln!("work");
+///     step();
+/// }
+///
+/// fn step() {
+///     iprintln!("step");
+/// }
+/// ```
+///
+/// This function is available only if the "std" feature is enabled.
+pub fn render_tree() -> String {
+    let recorded = lock_recorded();
+    let mut tree = String::new();
+    let has_lines = |thread: &ThreadId| {
+        recorded
+            .events
+            .iter()
+            .any(|(id, event)| id == thread && matches!(event, TraceEvent::Line { .. }))
+    };
+    let threads: Vec<_> = recorded
+        .threads
+        .iter()
+        .filter(|(thread, _)| has_lines(thread))
+        .collect();
+    let labeled = threads.len() > 1;
+    for (thread, name) in threads {
+        if labeled {
+            tree.push_str(name);
+            tree.push_str(":\n");
+        }
+        let events = recorded.events.iter().filter(|(id, _)| id == thread);
+        render_thread(events.map(|(_, event)| event), &mut tree);
+    }
+    tree
+}
+
+/// Renders the messages among `events`, which all came from one thread.
+fn render_thread<'a>(events: impl Iterator<Item = &'a TraceEvent>, tree: &mut String) {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut roots = Vec::new();
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    for event in events {
+        let TraceEvent::Line { depth, text } = event else {
+            continue;
+        };
+        while open
+            .last()
+            .is_some_and(|&(open_depth, _)| open_depth >= *depth)
+        {
+            open.pop();
+        }
+        let index = nodes.len();
+        match open.last() {
+            Some(&(_, parent)) => nodes[parent].children.push(index),
+            None => roots.push(index),
+        }
+        nodes.push(Node {
+            text: text.clone(),
+            children: Vec::new(),
+        });
+        open.push((*depth, index));
+    }
+    for &root in &roots {
+        for line in nodes[root].text.lines() {
+            tree.push_str(line);
+            tree.push('\n');
+        }
+        render_children(&nodes, root, "", tree);
+    }
+}
+
+fn render_children(nodes: &[Node], parent: usize, prefix: &str, tree: &mut String) {
+    let children = &nodes[parent].children;
+    for (i, &child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, guide) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let nested = alloc::format!("{}{}", prefix, guide);
+        for (j, line) in nodes[child].text.lines().enumerate() {
+            if j == 0 {
+                tree.push_str(prefix);
+                tree.push_str(branch);
+            } else {
+                tree.push_str(&nested);
+            }
+            tree.push_str(line);
+            tree.push('\n');
+        }
+        render_children(nodes, child, &nested, tree);
+    }
+}
@@ -0,0 +1,714 @@
+use crate::config::{
+    collapse_depth, continuation_extra_indent, escape_control_chars, generation, gutter_enabled,
+    indent_blank_lines, indent_char, indent_str, indent_style, indent_width, line_separator,
+    max_depth, max_line_len, preserve_line_endings, ruler_interval, wrap_width, IndentStyle,
+};
+use crate::sync::local;
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+/// The deepest depth whose prefixes are cached.
+const CACHED_DEPTHS: usize = 64;
+
+/// The prefixes of the first and the continuation lines at a depth.
+struct Prefixes {
+    first: String,
+    rest: String,
+}
+
+/// The prefixes of each depth, built with the settings of `generation`.
+struct PrefixCache {
+    generation: usize,
+    depths: Vec<Option<Prefixes>>,
+}
+
+local!(
+    static PREFIX_CACHE: RefCell<PrefixCache> = RefCell::new(PrefixCache {
+        generation: 0,
+        depths: Vec::new(),
+    })
+);
+
+/// Returns the prefix of a line at `depth`, which differs between the first
+/// line of a message and its continuation lines in the tree style.
+pub(crate) fn prefix(depth: usize, first_line: bool) -> String {
+    prefix_with(depth, first_line, None)
+}
+
+/// Returns the prefix of a line at `depth` like [`prefix`], with `unit`
+/// replacing the configured width and character of a level if given.
+fn prefix_with(depth: usize, first_line: bool, unit: Option<(usize, char)>) -> String {
+    let levels = if first_line {
+        depth
+    } else {
+        depth.saturating_add(continuation_extra_indent())
+    };
+    let mut prefix = String::new();
+    if gutter_enabled() {
+        prefix = alloc::format!("{:>3}│ ", depth);
+    }
+    match collapse_depth() {
+        Some(cap) if levels > cap => {
+            prefix.push_str(&indentation(cap, first_line, unit));
+            prefix.push_str(if first_line { "… " } else { "  " });
+        }
+        _ => prefix.push_str(&indentation(levels, first_line, unit)),
+    }
+    prefix
+}
+
+/// Returns the indentation of a line at `depth`, without the gutter.
+fn indentation(depth: usize, first_line: bool, unit: Option<(usize, char)>) -> String {
+    let depth = depth.min(max_depth());
+    #[cfg(feature = "std")]
+    if let Some(prefix) = crate::config::indent_fn_prefix(depth) {
+        return prefix;
+    }
+    let (width, fill) = unit.unwrap_or_else(|| (indent_width(), indent_char()));
+    match indent_style() {
+        IndentStyle::Spaces => match indent_str().filter(|_| unit.is_none()) {
+            Some(indent) => indent.repeat(depth),
+            None => match ruler_interval() {
+                Some(interval) if interval > 0 => ruled(depth, interval, width, fill),
+                _ => pad(fill, width * depth),
+            },
+        },
+        IndentStyle::Tabs => "\t".repeat(depth),
+        IndentStyle::Tree => {
+            if depth == 0 {
+                return String::new();
+            }
+            let mut prefix = tree_level(width, '│', ' ').repeat(depth - 1);
+            if first_line {
+                prefix.push_str(&tree_level(width, '├', '─'));
+            } else {
+                prefix.push_str(&tree_level(width, '│', ' '));
+            }
+            prefix
+        }
+        IndentStyle::Marker => alloc::format!("[L{}] ", depth),
+    }
+}
+
+/// Returns `columns` columns filled with `fill`.
+fn pad(fill: char, columns: usize) -> String {
+    #[cfg(feature = "unicode-width")]
+    {
+        let char_width = unicode_width::UnicodeWidthChar::width(fill).unwrap_or(0);
+        if char_width > 1 {
+            let mut padding: String = core::iter::repeat_n(fill, columns / char_width).collect();
+            padding.extend(core::iter::repeat_n(' ', columns % char_width));
+            return padding;
+        }
+    }
+    core::iter::repeat_n(fill, columns).collect()
+}
+
+/// Returns the padding of `depth` levels, with the last column of every
+/// `interval`th level drawn as a guide line.
+fn ruled(depth: usize, interval: usize, width: usize, fill: char) -> String {
+    let level = pad(fill, width);
+    let mut ruler = level.clone();
+    if ruler.pop().is_some() {
+        ruler.push('¦');
+    }
+    let mut padding = String::new();
+    for level_number in 1..=depth {
+        padding.push_str(if level_number % interval == 0 {
+            &ruler
+        } else {
+            &level
+        });
+    }
+    padding
+}
+
+fn tree_level(width: usize, guide: char, fill: char) -> String {
+    let mut level = String::new();
+    if width > 0 {
+        level.push(guide);
+    }
+    for column in 2..=width {
+        level.push(if column == width { ' ' } else { fill });
+    }
+    level
+}
+
+/// Returns the indentation that [`iformat!`](crate::iformat) would put in
+/// front of a message in the calling function.
+///
+/// The prefix follows the configured width, character and style, which makes
+/// it possible to line up manually formatted output with the indented one.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{current_indent, iformat};
+///
+/// fn main() {
+///     assert_eq!(current_indent(), "");
+///     nested();
+/// }
+///
+/// fn nested() {
+///     let pad = current_indent();
+///     assert_eq!(format!("{pad}custom stuff"), iformat!("custom stuff"));
+/// }
+/// ```
+#[inline(always)]
+pub fn current_indent() -> String {
+    indent_prefix(crate::call_depth!())
+}
+
+/// Returns the indentation that [`iformat!`](crate::iformat) puts in front of
+/// a message at `depth`.
+///
+/// The prefix follows the configured width, character and style. Together
+/// with [`call_depth!`](crate::call_depth) at the log site, this allows
+/// indenting the output of any logging backend, e.g. in a custom format
+/// function.
+///
+/// # Example
+///
+/// ```
+/// use iprint::indent_prefix;
+///
+/// assert_eq!(indent_prefix(0), "");
+/// assert_eq!(indent_prefix(2), "        ");
+/// ```
+pub fn indent_prefix(depth: usize) -> String {
+    with_prefixes(depth, |first, _| String::from(first))
+}
+
+#[doc(hidden)]
+pub fn indent_text(depth: usize, text: &str) -> String {
+    let mut buf = String::new();
+    indent_text_into(depth, text, &mut buf);
+    buf
+}
+
+#[doc(hidden)]
+pub fn indent_text_with(depth: usize, text: &str, unit: Option<(usize, char)>) -> String {
+    let Some(unit) = unit else {
+        return indent_text(depth, text);
+    };
+    let text = escape_controls(text);
+    let mut buf = String::new();
+    let first = prefix_with(depth, true, Some(unit));
+    let rest = prefix_with(depth, false, Some(unit));
+    indent_lines(&first, &rest, &wrap(&text, &first, &rest), &mut buf);
+    buf
+}
+
+#[doc(hidden)]
+pub fn indent_text_into(depth: usize, text: &str, buf: &mut String) {
+    let text = escape_controls(text);
+    with_prefixes(depth, |first, rest| {
+        indent_lines(first, rest, &wrap(&text, first, rest), buf)
+    });
+}
+
+#[doc(hidden)]
+pub fn indent_args_into(depth: usize, args: fmt::Arguments<'_>, buf: &mut String) {
+    let mut writer = IndentInto::new(depth, buf);
+    let _ = fmt::Write::write_fmt(&mut writer, args);
+    writer.finish();
+}
+
+#[doc(hidden)]
+pub fn unindented_args_into(args: fmt::Arguments<'_>, buf: &mut String) {
+    let _ = fmt::Write::write_fmt(buf, args);
+}
+
+/// Indents text as it is formatted, appending it to a `String` the same way
+/// [`indent_text_into`] appends the finished text.
+///
+/// Every character passes through two steps: the escaping of control
+/// characters, and the assembly of the lines with their prefixes, wrapping
+/// and truncation. Carriage returns are held back by both, since whether they
+/// belong to a line break is only known from what follows them.
+struct IndentInto<'a> {
+    buf: &'a mut String,
+    depth: usize,
+    escape: bool,
+    preserve: bool,
+    indent_blank: bool,
+    max_len: usize,
+    separator: &'static str,
+    /// The characters that fit after the prefix of the first and of the
+    /// continuation lines, if wrapping.
+    rooms: Option<(usize, usize)>,
+    /// Whether a carriage return waits to be escaped or not.
+    held_cr: bool,
+    /// The carriage returns at the current end of the line.
+    trailing_crs: usize,
+    /// The number of finished lines.
+    lines: usize,
+    line_open: bool,
+    line_start: usize,
+    prefixed: bool,
+    content_start: usize,
+    chars: usize,
+}
+
+impl<'a> IndentInto<'a> {
+    fn new(depth: usize, buf: &'a mut String) -> Self {
+        let rooms = wrap_width().map(|width| {
+            with_prefixes(depth, |first, rest| {
+                let room = |prefix: &str| width.saturating_sub(prefix.chars().count()).max(1);
+                (room(first), room(rest))
+            })
+        });
+        IndentInto {
+            buf,
+            depth,
+            escape: escape_control_chars(),
+            preserve: preserve_line_endings(),
+            indent_blank: indent_blank_lines(),
+            max_len: max_line_len(),
+            separator: line_separator(),
+            rooms,
+            held_cr: false,
+            trailing_crs: 0,
+            lines: 0,
+            line_open: false,
+            line_start: 0,
+            prefixed: false,
+            content_start: 0,
+            chars: 0,
+        }
+    }
+
+    /// Escapes `c` if needed, like [`escape_controls`].
+    fn escape_char(&mut self, c: char) {
+        if !self.escape {
+            return self.put(c);
+        }
+        if core::mem::take(&mut self.held_cr) {
+            if c == '\n' {
+                self.put('\r');
+            } else {
+                self.put_escaped('\r');
+            }
+        }
+        match c {
+            '\r' => self.held_cr = true,
+            '\n' => self.put('\n'),
+            c if c.is_control() => self.put_escaped(c),
+            c => self.put(c),
+        }
+    }
+
+    fn put_escaped(&mut self, c: char) {
+        if c.is_ascii() {
+            let code = u32::from(c);
+            let hex = |digit| core::char::from_digit(digit, 16).unwrap_or('0');
+            for c in ['\\', 'x', hex(code >> 4), hex(code & 0xf)] {
+                self.put(c);
+            }
+        } else {
+            for c in c.escape_unicode() {
+                self.put(c);
+            }
+        }
+    }
+
+    /// Adds `c` to the current line, like [`wrap`] and [`indent_lines`].
+    fn put(&mut self, c: char) {
+        self.open_line();
+        match c {
+            '\r' => self.trailing_crs += 1,
+            '\n' => self.end_line(true),
+            c => {
+                self.flush_crs();
+                self.push_content(c);
+            }
+        }
+    }
+
+    /// Adds a run of characters without line breaks or control characters.
+    fn put_plain(&mut self, text: &str) {
+        if core::mem::take(&mut self.held_cr) {
+            self.put_escaped('\r');
+        }
+        self.open_line();
+        self.flush_crs();
+        if !self.prefixed {
+            self.push_prefix();
+        }
+        self.buf.push_str(text);
+    }
+
+    /// Adds the carriage returns that turned out not to end the line.
+    fn flush_crs(&mut self) {
+        for _ in 0..core::mem::take(&mut self.trailing_crs) {
+            self.push_content('\r');
+        }
+    }
+
+    fn open_line(&mut self) {
+        if self.line_open {
+            return;
+        }
+        if self.lines > 0 && !self.preserve {
+            self.buf.push_str(self.separator);
+        }
+        self.line_open = true;
+        self.line_start = self.buf.len();
+        self.prefixed = false;
+        self.chars = 0;
+    }
+
+    fn push_content(&mut self, c: char) {
+        if let Some((first_room, rest_room)) = self.rooms {
+            let room = if self.lines == 0 {
+                first_room
+            } else {
+                rest_room
+            };
+            if self.chars == room {
+                // Like in `wrap`, the carriage returns at the end of the
+                // segment become part of the inserted line break.
+                let content = &self.buf[self.content_start..];
+                let crs = content.len() - content.trim_end_matches('\r').len();
+                self.buf.truncate(self.buf.len() - crs);
+                if self.buf.len() == self.content_start {
+                    self.buf.truncate(self.line_start);
+                    self.prefixed = false;
+                }
+                self.trailing_crs = crs;
+                self.end_line(true);
+                self.open_line();
+            }
+        }
+        if !self.prefixed {
+            self.push_prefix();
+        }
+        self.buf.push(c);
+        self.chars += 1;
+    }
+
+    fn push_prefix(&mut self) {
+        let first_line = self.lines == 0;
+        let buf = &mut *self.buf;
+        with_prefixes(self.depth, |first, rest| {
+            buf.push_str(if first_line { first } else { rest })
+        });
+        self.prefixed = true;
+        self.content_start = self.buf.len();
+    }
+
+    /// Ends the current line, with a line feed or at the end of the text.
+    fn end_line(&mut self, line_feed: bool) {
+        let crs = core::mem::take(&mut self.trailing_crs);
+        if !self.preserve {
+            // `str::lines` only drops the carriage return right before the
+            // line feed, and wrapping doesn't count the others.
+            let kept = if line_feed {
+                crs.saturating_sub(1)
+            } else {
+                crs
+            };
+            if kept > 0 && !self.prefixed {
+                self.push_prefix();
+            }
+            for _ in 0..kept {
+                self.buf.push('\r');
+            }
+        }
+        if !self.prefixed && self.indent_blank {
+            self.push_prefix();
+        }
+        truncate_line(self.buf, self.line_start, self.max_len);
+        if self.preserve {
+            for _ in 0..crs {
+                self.buf.push('\r');
+            }
+            if line_feed {
+                self.buf.push('\n');
+            }
+        }
+        self.line_open = false;
+        self.lines += 1;
+    }
+
+    fn finish(mut self) {
+        if core::mem::take(&mut self.held_cr) {
+            self.put_escaped('\r');
+        }
+        if self.line_open {
+            self.end_line(false);
+        }
+    }
+}
+
+impl fmt::Write for IndentInto<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.rooms.is_some() {
+            s.chars().for_each(|c| self.escape_char(c));
+            return Ok(());
+        }
+        let escape = self.escape;
+        let mut rest = s;
+        while let Some(end) =
+            rest.find(|c: char| c == '\n' || c == '\r' || escape && c.is_control())
+        {
+            if end > 0 {
+                self.put_plain(&rest[..end]);
+            }
+            let c = rest[end..].chars().next().unwrap_or('\n');
+            self.escape_char(c);
+            rest = &rest[end + c.len_utf8()..];
+        }
+        if !rest.is_empty() {
+            self.put_plain(rest);
+        }
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn indent_cow(depth: usize, args: core::fmt::Arguments<'_>) -> Cow<'static, str> {
+    match args.as_str() {
+        Some(text) if fits_unindented(depth, text) => Cow::Borrowed(text),
+        Some(text) => Cow::Owned(indent_text(depth, text)),
+        None => Cow::Owned(indent_text(depth, &alloc::fmt::format(args))),
+    }
+}
+
+#[doc(hidden)]
+pub fn unindented_cow(args: core::fmt::Arguments<'_>) -> Cow<'static, str> {
+    match args.as_str() {
+        Some(text) => Cow::Borrowed(text),
+        None => Cow::Owned(alloc::fmt::format(args)),
+    }
+}
+
+/// Returns whether indenting `text` at `depth` would leave it unchanged.
+fn fits_unindented(depth: usize, text: &str) -> bool {
+    let max_len = max_line_len();
+    !text.contains('\n')
+        && (text.len() <= max_len || text.chars().count() <= max_len)
+        && with_prefixes(depth, |first, _| first.is_empty())
+        && wrap_width().is_none_or(|width| text.chars().count() <= width)
+        && matches!(escape_controls(text), Cow::Borrowed(_))
+}
+
+/// An iterator over the indented lines of a message, created with
+/// [`iformat_lines!`](crate::iformat_lines).
+///
+/// The lines are indented one at a time as the iterator advances, and are
+/// the lines that [`iformat!`](crate::iformat) would join into one string.
+pub struct IndentedLines {
+    text: String,
+    position: usize,
+    first: String,
+    rest: String,
+    indent_blank: bool,
+    max_len: usize,
+}
+
+impl IndentedLines {
+    #[doc(hidden)]
+    pub fn new(depth: usize, mut text: String) -> Self {
+        let (first, rest): (String, String) =
+            with_prefixes(depth, |first, rest| (first.into(), rest.into()));
+        if let Cow::Owned(escaped) = escape_controls(&text) {
+            text = escaped;
+        }
+        if let Cow::Owned(wrapped) = wrap(&text, &first, &rest) {
+            text = wrapped;
+        }
+        IndentedLines {
+            text,
+            position: 0,
+            first,
+            rest,
+            indent_blank: indent_blank_lines(),
+            max_len: max_line_len(),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn unindented(text: String) -> Self {
+        IndentedLines {
+            text,
+            position: 0,
+            first: String::new(),
+            rest: String::new(),
+            indent_blank: true,
+            max_len: usize::MAX,
+        }
+    }
+}
+
+impl Iterator for IndentedLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let first_line = self.position == 0;
+        let remaining = &self.text[self.position..];
+        if remaining.is_empty() {
+            return None;
+        }
+        // Splits like `str::lines`, which iformat! uses.
+        let line = match remaining.find('\n') {
+            Some(end) => {
+                self.position += end + 1;
+                let line = &remaining[..end];
+                line.strip_suffix('\r').unwrap_or(line)
+            }
+            None => {
+                self.position = self.text.len();
+                remaining
+            }
+        };
+        let mut indented = String::new();
+        if self.indent_blank || !line.is_empty() {
+            indented.push_str(if first_line { &self.first } else { &self.rest });
+        }
+        indented.push_str(line);
+        truncate_line(&mut indented, 0, self.max_len);
+        Some(indented)
+    }
+}
+
+/// Calls `f` with the prefixes of the first and the continuation lines at
+/// `depth`, which are cached per thread for repeated calls at the same depth.
+fn with_prefixes<R>(depth: usize, f: impl FnOnce(&str, &str) -> R) -> R {
+    if depth >= CACHED_DEPTHS {
+        return f(&prefix(depth, true), &prefix(depth, false));
+    }
+    let generation = generation();
+    let mut f = Some(f);
+    let cached = PREFIX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.generation != generation {
+            cache.generation = generation;
+            cache.depths.clear();
+        }
+        let prefixes = cache.depths.get(depth)?.as_ref()?;
+        let f = f.take()?;
+        Some(f(&prefixes.first, &prefixes.rest))
+    });
+    if let Some(result) = cached {
+        return result;
+    }
+    // Built outside of the borrow, in case a setting calls back into iprint.
+    let prefixes = Prefixes {
+        first: prefix(depth, true),
+        rest: prefix(depth, false),
+    };
+    let result = f.take().unwrap()(&prefixes.first, &prefixes.rest);
+    PREFIX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.generation == generation {
+            if cache.depths.len() <= depth {
+                cache.depths.resize_with(depth + 1, || None);
+            }
+            cache.depths[depth] = Some(prefixes);
+        }
+    });
+    result
+}
+
+fn indent_lines(first: &str, rest: &str, text: &str, buf: &mut String) {
+    let breaks = text.matches('\n').count();
+    buf.reserve(text.len() + first.len() + breaks * rest.len());
+    let indent_blank = indent_blank_lines();
+    let max_len = max_line_len();
+    if preserve_line_endings() {
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let content = line.trim_end_matches(['\r', '\n']);
+            let start = buf.len();
+            if indent_blank || !content.is_empty() {
+                buf.push_str(if i == 0 { first } else { rest });
+            }
+            buf.push_str(content);
+            truncate_line(buf, start, max_len);
+            buf.push_str(&line[content.len()..]);
+        }
+        return;
+    }
+    let separator = line_separator();
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            buf.push_str(separator);
+        }
+        let start = buf.len();
+        if indent_blank || !line.is_empty() {
+            buf.push_str(if i == 0 { first } else { rest });
+        }
+        buf.push_str(line);
+        truncate_line(buf, start, max_len);
+    }
+}
+
+/// Replaces the control characters of `text` other than line breaks with
+/// escapes, if [enabled](crate::set_escape_control_chars).
+fn escape_controls(text: &str) -> Cow<'_, str> {
+    let is_escaped = |(i, c): (usize, char)| {
+        c.is_control() && c != '\n' && !(c == '\r' && text[i + 1..].starts_with('\n'))
+    };
+    if !escape_control_chars() || !text.char_indices().any(is_escaped) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for (i, c) in text.char_indices() {
+        if !is_escaped((i, c)) {
+            escaped.push(c);
+        } else if c.is_ascii() {
+            escaped.push_str(&alloc::format!("\\x{:02x}", u32::from(c)));
+        } else {
+            escaped.extend(c.escape_unicode());
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Breaks every line of `text` that doesn't fit into the
+/// [wrap width](wrap_width) after its prefix into several lines.
+fn wrap<'a>(text: &'a str, first: &str, rest: &str) -> Cow<'a, str> {
+    let Some(width) = wrap_width() else {
+        return Cow::Borrowed(text);
+    };
+    let first_room = width.saturating_sub(first.chars().count()).max(1);
+    let rest_room = width.saturating_sub(rest.chars().count()).max(1);
+    let fits = text.split('\n').enumerate().all(|(i, line)| {
+        let room = if i == 0 { first_room } else { rest_room };
+        line.len() <= room || line.chars().count() <= room
+    });
+    if fits {
+        return Cow::Borrowed(text);
+    }
+    let mut wrapped = String::with_capacity(text.len() + text.len() / rest_room);
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let mut content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let mut room = if i == 0 { first_room } else { rest_room };
+        while let Some((end, _)) = content.char_indices().nth(room) {
+            wrapped.push_str(&content[..end]);
+            wrapped.push('\n');
+            content = &content[end..];
+            room = rest_room;
+        }
+        wrapped.push_str(content);
+        wrapped.push_str(ending);
+    }
+    Cow::Owned(wrapped)
+}
+
+/// Cuts the line at the end of `buf`, starting at `start`, down to `max_len`
+/// characters followed by an ellipsis.
+fn truncate_line(buf: &mut String, start: usize, max_len: usize) {
+    if buf.len() - start <= max_len {
+        return;
+    }
+    if let Some((end, _)) = buf[start..].char_indices().nth(max_len) {
+        buf.truncate(start + end);
+        buf.push('…');
+    }
+}
@@ -0,0 +1,76 @@
+//! Storage for the state of the crate that works with and without `std`.
+//!
+//! With `std`, per-thread state lives in `thread_local!`s and settings behind
+//! an `RwLock`. Without it there's only a single context of execution as far as
+//! the crate is concerned, so both are plain statics guarded by a critical
+//! section.
+
+/// Declares a static that is local to the current thread with `std`, and
+/// global otherwise. Either way it's accessed through `with`.
+macro_rules! local {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        #[cfg(feature = "std")]
+        std::thread_local!($(#[$attr])* $vis static $name: $t = const { $init });
+        #[cfg(not(feature = "std"))]
+        $(#[$attr])* $vis static $name: $crate::sync::GlobalKey<$t> =
+            $crate::sync::GlobalKey::new($init);
+    };
+}
+pub(crate) use local;
+
+/// The `no_std` replacement of `std::thread::LocalKey`.
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+pub struct GlobalKey<T> {
+    inner: critical_section::Mutex<T>,
+}
+
+#[cfg(not(feature = "std"))]
+impl<T> GlobalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(value: T) -> Self {
+        GlobalKey {
+            inner: critical_section::Mutex::new(value),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        critical_section::with(|cs| f(self.inner.borrow(cs)))
+    }
+}
+
+/// A global setting that can be read and replaced as a whole.
+pub(crate) struct Setting<T> {
+    #[cfg(feature = "std")]
+    inner: std::sync::RwLock<T>,
+    #[cfg(not(feature = "std"))]
+    inner: critical_section::Mutex<core::cell::Cell<T>>,
+}
+
+impl<T: Copy> Setting<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Setting {
+            #[cfg(feature = "std")]
+            inner: std::sync::RwLock::new(value),
+            #[cfg(not(feature = "std"))]
+            inner: critical_section::Mutex::new(core::cell::Cell::new(value)),
+        }
+    }
+
+    pub(crate) fn get(&self) -> T {
+        #[cfg(feature = "std")]
+        return *self.inner.read().unwrap();
+        #[cfg(not(feature = "std"))]
+        critical_section::with(|cs| self.inner.borrow(cs).get())
+    }
+
+    pub(crate) fn set(&self, value: T) {
+        #[cfg(feature = "std")]
+        {
+            *self.inner.write().unwrap() = value;
+        }
+        #[cfg(not(feature = "std"))]
+        critical_section::with(|cs| self.inner.borrow(cs).set(value));
+    }
+}
@@ -0,0 +1,78 @@
+use std::cell::Cell;
+use std::future::Future;
+
+tokio::task_local! {
+    static ASYNC_DEPTH: Cell<usize>;
+}
+
+/// Runs a future with its own async call depth, starting at zero.
+///
+/// The depth is stored in a tokio task-local, so it follows the future across
+/// `.await` points and threads. [`async_scope`] guards only have an effect
+/// inside a future wrapped by this function. Spawned tasks don't inherit the
+/// depth, and need to be wrapped separately.
+///
+/// This function is available only if the "async" feature is enabled.
+pub async fn with_async_depth<F: Future>(future: F) -> F::Output {
+    ASYNC_DEPTH.scope(Cell::new(0), future).await
+}
+
+/// Increases the async call depth of the current task for as long as it is
+/// alive.
+///
+/// Unlike a [`DepthGuard`](crate::DepthGuard), this guard can be held across
+/// `.await` points. Create it with [`async_scope`].
+///
+/// This type is available only if the "async" feature is enabled.
+#[must_use = "the depth is restored as soon as the guard is dropped"]
+pub struct AsyncDepthGuard {
+    _private: (),
+}
+
+/// Enters a new indentation level of the current task that lasts until the
+/// returned guard is dropped.
+///
+/// While the async depth is non-zero, [`call_depth!`](crate::call_depth)
+/// reports it instead of inspecting the stack pointer. Outside of
+/// [`with_async_depth`] this has no effect.
+///
+/// # Example
+///
+/// ```
+/// #[cfg(feature = "async")]
+/// {
+///     use iprint::{async_scope, call_depth, iformat, with_async_depth};
+///
+///     async fn outer() {
+///         let _guard = async_scope();
+///         tokio::task::yield_now().await;
+///         assert_eq!(call_depth!(), 1);
+///         inner().await;
+///     }
+///
+///     async fn inner() {
+///         let _guard = async_scope();
+///         tokio::task::yield_now().await;
+///         assert_eq!(iformat!("inner"), "        inner");
+///     }
+///
+///     let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+///     runtime.block_on(with_async_depth(outer()));
+/// }
+/// ```
+///
+/// This function is available only if the "async" feature is enabled.
+pub fn async_scope() -> AsyncDepthGuard {
+    let _ = ASYNC_DEPTH.try_with(|d| d.set(d.get() + 1));
+    AsyncDepthGuard { _private: () }
+}
+
+impl Drop for AsyncDepthGuard {
+    fn drop(&mut self) {
+        let _ = ASYNC_DEPTH.try_with(|d| d.set(d.get().saturating_sub(1)));
+    }
+}
+
+pub(crate) fn async_depth() -> usize {
+    ASYNC_DEPTH.try_with(|d| d.get()).unwrap_or(0)
+}
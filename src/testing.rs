@@ -0,0 +1,76 @@
+//! Helpers for testing code that prints with iprint.
+//!
+//! This module is available only if the "testing" feature is enabled.
+
+use alloc::string::String;
+use core::cell::RefCell;
+
+std::thread_local! {
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` and returns everything the printing macros wrote to stdout on the
+/// current thread in the meantime, instead of printing it.
+///
+/// The output is the text that would have been printed, like for an
+/// [output sink](crate::set_output_sink), which the capture takes precedence
+/// over. Unlike a sink, a capture only applies to the current thread, so
+/// tests running in parallel don't see each other's output. The body of the
+/// closure starts at depth 0 like with [`with_anchor`](crate::with_anchor),
+/// no matter how deep the test itself runs, which keeps the captured
+/// indentation deterministic. Captures can be nested, and the previous one is
+/// restored even if `f` panics.
+///
+/// Output to stderr is not captured.
+///
+/// # Example
+///
+/// ```
+/// use iprint::iprintln;
+/// use iprint::testing::capture;
+///
+/// fn traced() {
+///     iprintln!("start");
+///     step();
+/// }
+///
+/// fn step() {
+///     iprintln!("step");
+/// }
+///
+/// fn main() {
+///     iprintln!("not captured");
+///     assert_eq!(capture(|| traced()), "start\n    step\n");
+///     assert_eq!(capture(traced), "start\n    step\n");
+/// }
+/// ```
+pub fn capture(f: impl FnOnce()) -> String {
+    struct Restore(Option<String>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CAPTURED.with(|captured| *captured.borrow_mut() = self.0.take());
+        }
+    }
+
+    let restore = Restore(CAPTURED.with(|captured| captured.replace(Some(String::new()))));
+    crate::with_anchor(f);
+    let output = CAPTURED.with(|captured| captured.borrow_mut().take());
+    drop(restore);
+    output.unwrap_or_default()
+}
+
+/// Appends `text`, and a newline if `newline` is set, to the capture of the
+/// current thread, and returns whether one is active.
+pub(crate) fn capture_text(text: &str, newline: bool) -> bool {
+    CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(output) => {
+            output.push_str(text);
+            if newline {
+                output.push('\n');
+            }
+            true
+        }
+        None => false,
+    })
+}
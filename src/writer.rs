@@ -0,0 +1,332 @@
+use core::fmt;
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io;
+
+/// An [`io::Write`] adapter that indents every line written through it.
+///
+/// The depth is taken from the function that creates the writer, the same
+/// way [`iformat!`](crate::iformat) would determine it there, and stays fixed
+/// for the writer's lifetime. This makes it possible to indent the output of
+/// code that writes to any `impl Write` without changing that code.
+///
+/// Lines are buffered until their newline arrives, so a line split across
+/// several `write` calls is still prefixed only once. A pending partial line
+/// is written out on [`flush`](io::Write::flush) and when the writer is
+/// dropped. The first line written since the writer was created or last
+/// flushed gets the prefix of the first line of a message, and the lines
+/// after it that of continuation lines, so the prefixes don't depend on how
+/// the output is split into `write` calls.
+///
+/// An error of the wrapped writer is returned by the next call to `write` or
+/// `flush`, once the bytes taken in before it are accounted for.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, IndentWriter};
+/// use std::io::Write;
+///
+/// fn report(out: &mut impl Write) -> std::io::Result<()> {
+///     write!(out, "par")?;
+///     write!(out, "tial\nsecond\n")
+/// }
+///
+/// fn nested(buf: &mut Vec<u8>) -> std::io::Result<()> {
+///     let mut w = IndentWriter::new(buf);
+///     report(&mut w)
+/// }
+///
+/// fn main() -> std::io::Result<()> {
+///     iformat!("top");
+///     let mut buf = Vec::new();
+///     nested(&mut buf)?;
+///     assert_eq!(String::from_utf8(buf).unwrap(), "    partial\n    second\n");
+///     Ok(())
+/// }
+/// ```
+///
+/// In the tree style, the same text gets the same prefixes in one call and
+/// in several:
+///
+/// ```
+/// use iprint::{set_indent_style, IndentStyle, IndentWriter};
+/// use std::io::Write;
+///
+/// fn indented(pieces: &[&str]) -> String {
+///     let mut w = IndentWriter::with_depth(Vec::new(), 1);
+///     for piece in pieces {
+///         w.write_all(piece.as_bytes()).unwrap();
+///     }
+///     w.flush().unwrap();
+///     String::from_utf8(w.get_ref().clone()).unwrap()
+/// }
+///
+/// set_indent_style(IndentStyle::Tree);
+/// let whole = indented(&["a\nb\nc\n"]);
+/// assert_eq!(whole, "├── a\n│   b\n│   c\n");
+/// assert_eq!(indented(&["a\n", "b", "\nc\n"]), whole);
+/// assert_eq!(indented(&["a\nb\n", "c\n"]), whole);
+/// ```
+///
+/// A failing write of the wrapped writer still accepts the line it failed on,
+/// and reports the error on the next call:
+///
+/// ```
+/// use iprint::IndentWriter;
+/// use std::io::{self, Write};
+///
+/// struct Flaky {
+///     out: Vec<u8>,
+///     fail: bool,
+/// }
+///
+/// impl Write for Flaky {
+///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+///         if std::mem::take(&mut self.fail) {
+///             return Err(io::ErrorKind::Other.into());
+///         }
+///         self.out.extend_from_slice(buf);
+///         Ok(buf.len())
+///     }
+///
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let mut w = IndentWriter::with_depth(Flaky { out: Vec::new(), fail: true }, 1);
+/// assert_eq!(w.write(b"a\nb\n").unwrap(), 2);
+/// assert!(w.write(b"b\n").is_err());
+/// assert_eq!(w.write(b"b\n").unwrap(), 2);
+/// w.flush().unwrap();
+/// assert_eq!(w.get_ref().out, b"    a\n    b\n");
+/// ```
+///
+/// This type is available only if the "std" feature is enabled.
+#[cfg(feature = "std")]
+pub struct IndentWriter<W: io::Write> {
+    inner: W,
+    depth: usize,
+    line: Vec<u8>,
+    at_line_start: bool,
+    first_line: bool,
+    error: Option<io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> IndentWriter<W> {
+    /// Wraps `inner`, indenting by the call depth of the calling function.
+    #[inline(always)]
+    pub fn new(inner: W) -> Self {
+        Self::with_depth(inner, crate::call_depth!())
+    }
+
+    /// Wraps `inner`, indenting by a fixed `depth`.
+    pub fn with_depth(inner: W, depth: usize) -> Self {
+        IndentWriter {
+            inner,
+            depth,
+            line: Vec::new(),
+            at_line_start: true,
+            first_line: true,
+            error: None,
+        }
+    }
+
+    /// Returns the depth this writer indents by.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped writer.
+    ///
+    /// Writing to it directly bypasses the indentation and any buffered
+    /// partial line.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Buffers `bytes` of a line, prefixing them if they start a new line.
+    fn push(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        if self.at_line_start {
+            let prefix = crate::render::prefix(self.depth, self.first_line);
+            self.first_line = false;
+            self.line.extend_from_slice(prefix.as_bytes());
+        }
+        self.line.extend_from_slice(bytes);
+        self.at_line_start = bytes.ends_with(b"\n");
+    }
+
+    /// Writes the buffered bytes, keeping the ones that aren't written yet if
+    /// the wrapped writer fails.
+    fn write_line(&mut self) -> io::Result<()> {
+        while !self.line.is_empty() {
+            match self.inner.write(&self.line) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.line.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> io::Write for IndentWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+            let (line, tail) = rest.split_at(end + 1);
+            self.push(line);
+            rest = tail;
+            if let Err(error) = self.write_line() {
+                // The line is buffered, so it counts as written.
+                self.error = Some(error);
+                return Ok(buf.len() - rest.len());
+            }
+        }
+        self.push(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.write_line()?;
+        self.first_line = true;
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> Drop for IndentWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.write_line();
+    }
+}
+
+/// A [`fmt::Write`] adapter that indents every line written through it.
+///
+/// This is the counterpart of [`IndentWriter`] for formatting targets like
+/// a `String` or a [`fmt::Formatter`], which makes it easy to indent the
+/// multi-line output of a `Display` implementation. Like [`IndentWriter`],
+/// it takes its depth from the function that creates it.
+///
+/// Nothing is buffered: the prefix is written as soon as the first character
+/// of a line arrives. The first line gets the prefix of the first line of a
+/// message, and the lines after it that of continuation lines, however the
+/// text is split into `write_str` calls.
+///
+/// # Example
+///
+/// ```
+/// use iprint::{iformat, IndentFmtWriter};
+/// use std::fmt::Write;
+///
+/// fn nested(s: &mut String) {
+///     write!(IndentFmtWriter::new(s), "a\nb").unwrap();
+/// }
+///
+/// fn main() {
+///     iformat!("top");
+///     let mut s = String::new();
+///     nested(&mut s);
+///     assert_eq!(s, "    a\n    b");
+/// }
+/// ```
+///
+/// In the tree style, this draws a branch only before the first line, and
+/// the same text gets the same prefixes however `write!` splits it up:
+///
+/// ```
+/// use iprint::{set_indent_style, IndentFmtWriter, IndentStyle};
+/// use std::fmt::Write;
+///
+/// set_indent_style(IndentStyle::Tree);
+/// let mut whole = String::new();
+/// write!(IndentFmtWriter::with_depth(&mut whole, 1), "a\nb\nc\n").unwrap();
+/// assert_eq!(whole, "├── a\n│   b\n│   c\n");
+///
+/// let mut pieces = String::new();
+/// let mut w = IndentFmtWriter::with_depth(&mut pieces, 1);
+/// write!(w, "{}\n{}", 'a', "b\n").unwrap();
+/// write!(w, "c\n").unwrap();
+/// assert_eq!(pieces, whole);
+/// ```
+pub struct IndentFmtWriter<W: fmt::Write> {
+    inner: W,
+    depth: usize,
+    at_line_start: bool,
+    first_line: bool,
+}
+
+impl<W: fmt::Write> IndentFmtWriter<W> {
+    /// Wraps `inner`, indenting by the call depth of the calling function.
+    #[inline(always)]
+    pub fn new(inner: W) -> Self {
+        Self::with_depth(inner, crate::call_depth!())
+    }
+
+    /// Wraps `inner`, indenting by a fixed `depth`.
+    pub fn with_depth(inner: W, depth: usize) -> Self {
+        IndentFmtWriter {
+            inner,
+            depth,
+            at_line_start: true,
+            first_line: true,
+        }
+    }
+
+    /// Returns the depth this writer indents by.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns a reference to the wrapped writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for IndentFmtWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_inclusive('\n') {
+            if self.at_line_start {
+                let prefix = crate::render::prefix(self.depth, self.first_line);
+                self.first_line = false;
+                self.inner.write_str(&prefix)?;
+            }
+            self.inner.write_str(line)?;
+            self.at_line_start = line.ends_with('\n');
+        }
+        Ok(())
+    }
+}
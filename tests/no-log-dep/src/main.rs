@@ -0,0 +1,11 @@
+use iprint::{ialways_warn, idebug, ierror, iinfo, ilog, itrace, iwarn};
+
+fn main() {
+    iinfo!("connected");
+    iwarn!(target: "net", "retrying");
+    ierror!("failed {}", 1);
+    idebug!(id = 7; "request");
+    itrace!("done");
+    ialways_warn!("deep");
+    ilog!(iprint::ilog::log::Level::Info, "logged");
+}